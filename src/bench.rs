@@ -0,0 +1,234 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use anyhow::ensure;
+use log::{error, info};
+use rand::Rng;
+
+use crate::{interrupted, BotLaunchFailed, GameConfig, GameType, Shotgun};
+
+/// Bench games without a timeout stop at this frame (if the bots have a tournament module)...
+const BENCH_TIME_OUT_AT_FRAME: u32 = 240;
+/// ...or after this many seconds
+const BENCH_TIME_OUT_AT_SECONDS: u64 = 60;
+
+/// Plays the same game `games` times, measuring how long each phase took
+#[derive(Debug)]
+pub struct Bench {
+    pub games: u32,
+    /// Pause after a game ended, before the next one starts
+    pub inter_game_delay: Duration,
+}
+
+/// How long one phase of the games took
+#[derive(Debug)]
+struct Phase {
+    name: &'static str,
+    samples: Vec<Duration>,
+}
+
+impl Phase {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            samples: vec![],
+        }
+    }
+
+    /// Min, median and max, `None` without samples
+    fn stats(&self) -> Option<(Duration, Duration, Duration)> {
+        let mut samples = self.samples.clone();
+        samples.sort();
+        let len = samples.len();
+        // The middle one, or between the two in the middle
+        let median = (*samples.get(len.checked_sub(1)? / 2)? + samples[len / 2]) / 2;
+        Some((samples[0], median, *samples.last()?))
+    }
+}
+
+#[derive(Debug)]
+pub struct BenchSummary {
+    games: u32,
+    failed: u32,
+    /// Waiting for BWAPI to release the slots of the last game (see `inter_game_delay`)
+    cool_down: Phase,
+    /// Spawning StarCraft and the bots, until all of them are connected
+    launch: Phase,
+    /// From all bots running to the first frame (only with a tournament module)
+    lobby: Phase,
+    /// From the first frame (or all bots running) until the last StarCraft exited
+    game: Phase,
+    /// Collecting the results and cleaning up after the last StarCraft exited
+    teardown: Phase,
+    total: Phase,
+}
+
+impl Default for BenchSummary {
+    fn default() -> Self {
+        Self {
+            games: 0,
+            failed: 0,
+            cool_down: Phase::new("Cool down"),
+            launch: Phase::new("Launch"),
+            lobby: Phase::new("Lobby"),
+            game: Phase::new("Game"),
+            teardown: Phase::new("Teardown"),
+            total: Phase::new("Total"),
+        }
+    }
+}
+
+impl BenchSummary {
+    pub fn write_table(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "{} games ({} failed to launch)",
+            self.games, self.failed
+        )?;
+        writeln!(
+            out,
+            "{:10} {:>9} {:>9} {:>9}",
+            "Phase", "Min", "Median", "Max"
+        )?;
+        for phase in [
+            &self.cool_down,
+            &self.launch,
+            &self.lobby,
+            &self.game,
+            &self.teardown,
+            &self.total,
+        ] {
+            match phase.stats() {
+                Some((min, median, max)) => writeln!(
+                    out,
+                    "{:10} {:>8.2}s {:>8.2}s {:>8.2}s",
+                    phase.name,
+                    min.as_secs_f64(),
+                    median.as_secs_f64(),
+                    max.as_secs_f64()
+                )?,
+                None => writeln!(out, "{:10} {:>9} {:>9} {:>9}", phase.name, "-", "-", "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Bench {
+    /// Plays the game of `game_config` over and over, then prints how long the phases took.
+    /// Games without a timeout get a short one, the bots should be about as trivial as possible.
+    pub fn run(
+        &self,
+        shotgun: &mut Shotgun,
+        game_config: &GameConfig,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<()> {
+        let GameType::Melee(bots) = &game_config.game_type;
+        ensure!(!bots.is_empty(), "There are no bots to benchmark");
+        let mut game_config = game_config.clone();
+        if game_config.time_out_at_frame.is_none() && game_config.time_out_at_seconds.is_none() {
+            info!(
+                "Stopping each game at frame {} or after {} seconds",
+                BENCH_TIME_OUT_AT_FRAME, BENCH_TIME_OUT_AT_SECONDS
+            );
+            game_config.time_out_at_frame = Some(BENCH_TIME_OUT_AT_FRAME);
+            game_config.time_out_at_seconds = Some(BENCH_TIME_OUT_AT_SECONDS);
+        }
+        let mut summary = BenchSummary::default();
+        for game_number in 0..self.games {
+            if interrupted() {
+                break;
+            }
+            if game_number > 0 {
+                let cooling_down = Instant::now();
+                shotgun.cool_down(&game_config, self.inter_game_delay, bots.len())?;
+                summary.cool_down.samples.push(cooling_down.elapsed());
+            }
+            let mut game = game_config.clone();
+            game.resolve_map_pool(&shotgun.starcraft_path, rng)?;
+            game.resolve_game_name(rng);
+            info!("Game {} of {}", game_number + 1, self.games);
+            summary.games += 1;
+            let playing = Instant::now();
+            let timings = match shotgun.play(&game, rng) {
+                Ok(Some(result)) => result.timings,
+                Ok(None) => continue,
+                Err(e) if interrupted() => return Err(e),
+                Err(e) => match e.downcast_ref::<BotLaunchFailed>() {
+                    Some(_) => {
+                        error!("{:?}", e);
+                        summary.failed += 1;
+                        continue;
+                    }
+                    None => return Err(e),
+                },
+            };
+            let total = playing.elapsed();
+            summary.launch.samples.push(timings.launched);
+            if let Some(started) = timings.started {
+                summary.lobby.samples.push(started - timings.launched);
+            }
+            summary.game.samples.push(
+                timings
+                    .ended
+                    .saturating_sub(timings.started.unwrap_or(timings.launched)),
+            );
+            summary
+                .teardown
+                .samples
+                .push(total.saturating_sub(timings.ended));
+            summary.total.samples.push(total);
+        }
+
+        if game_config.dry_run {
+            return Ok(());
+        }
+        summary.write_table(&mut std::io::stdout())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bench::{BenchSummary, Phase};
+    use std::time::Duration;
+
+    #[test]
+    fn test_phase_stats() {
+        let mut phase = Phase::new("Launch");
+        assert_eq!(phase.stats(), None);
+        phase.samples = vec![Duration::from_secs(3)];
+        assert_eq!(
+            phase.stats(),
+            Some((
+                Duration::from_secs(3),
+                Duration::from_secs(3),
+                Duration::from_secs(3)
+            ))
+        );
+        phase.samples = [4, 1, 3, 2].map(Duration::from_secs).to_vec();
+        assert_eq!(
+            phase.stats(),
+            Some((
+                Duration::from_secs(1),
+                Duration::from_millis(2500),
+                Duration::from_secs(4)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_bench_table() {
+        let mut summary = BenchSummary {
+            games: 2,
+            ..Default::default()
+        };
+        summary.launch.samples = vec![Duration::from_millis(1500), Duration::from_millis(2500)];
+        let mut table = vec![];
+        summary.write_table(&mut table).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        assert!(table.starts_with("2 games (0 failed to launch)\nPhase"));
+        assert!(table.contains("Launch         1.50s     2.00s     2.50s\n"));
+        assert!(table.contains("Lobby              -         -         -\n"));
+    }
+}
@@ -1,10 +1,15 @@
-use crate::{GameConfig, Race, SandboxMode};
-use anyhow::bail;
-use std::fs::read_dir;
-use std::path::{Path, PathBuf};
+use crate::bwapi::sanitize_character_name;
+use crate::setup::unzip;
+use crate::{BwapiIni, GameConfig, Race, SandboxMode};
+use anyhow::{bail, Context};
+use log::info;
+use std::fs::{create_dir_all, read_dir, File};
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
 
 pub trait LaunchBuilder {
+    /// The BWAPI.ini `build_command` will write for this bot
+    fn bwapi_ini(&self) -> BwapiIni;
     fn build_command(&self, game_config: &GameConfig) -> anyhow::Result<Command>;
 }
 
@@ -14,16 +19,89 @@ pub struct BotSetup {
     pub starcraft_path: PathBuf,
     pub player_name: String,
     pub bot_binary: Binary,
+    /// Debug build of a DLL bot, for BWAPI's `ai_dbg`
+    pub debug_binary: Option<PathBuf>,
+    /// The bot folder, relative paths of the bot are resolved against it and bots run in it
     pub bot_base_path: PathBuf,
+    /// Folder the BWAPI.ini is written to
+    pub ini_dir: PathBuf,
     pub tournament_module: Option<PathBuf>,
+    pub replay_template: Option<String>,
+    /// Folder `replay_template` is relative to, instead of the bot folder
+    pub replay_root: Option<PathBuf>,
     pub race: Race,
+    /// Joins without a race
+    pub observer: bool,
+    pub game_speed: i32,
+    pub sound: bool,
+    /// BWAPI's `auto_restart`, only used by launchers where BWAPI manages the menus
+    pub auto_restart: bool,
     pub sandbox: SandboxMode,
+    /// The bot's own BWAPI.ini, to keep its settings
+    pub own_bwapi_ini: Option<String>,
+}
+
+impl BotSetup {
+    pub fn bwapi_ini_path(&self) -> PathBuf {
+        self.ini_dir.join("bwapi.ini")
+    }
+
+    /// Writes the BWAPI.ini for this bot to `bwapi_ini_path`
+    pub fn write_bwapi_ini(&self, bwapi_ini: &BwapiIni) -> anyhow::Result<PathBuf> {
+        let path = self.bwapi_ini_path();
+        create_dir_all(&self.ini_dir)
+            .with_context(|| format!("Could not create '{}'", self.ini_dir.to_string_lossy()))?;
+        let mut file = File::create(&path)?;
+        match &self.own_bwapi_ini {
+            Some(own) => bwapi_ini.write_merged(own, &mut file),
+            None => bwapi_ini.write(&mut file),
+        }
+        .with_context(|| format!("Could not write '{}'", path.to_string_lossy()))?;
+        Ok(path)
+    }
+
+    /// Resolves `path` relative to the bot folder. Paths with a root are kept as they are, even without
+    /// a drive letter (see the BWAPI 3.7.x workaround). Resolved ones lose the drive letter too.
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        if path.has_root() || path.is_absolute() {
+            return path.to_path_buf();
+        }
+        let resolved = self.bot_base_path.join(path);
+        let mut components = resolved.components();
+        if let Some(Component::Prefix(_)) = components.clone().next() {
+            components.next();
+        }
+        components.as_path().to_path_buf()
+    }
+
+    /// Race to join LAN games with, observers leave it to BWAPI (which joins them as Random)
+    pub fn lan_race(&self) -> Option<Race> {
+        (!self.observer).then_some(self.race)
+    }
+
+    /// `player_name`, or the name of the bot binary if there is none
+    pub fn character_name(&self) -> String {
+        if !self.player_name.is_empty() {
+            return self.player_name.clone();
+        }
+        let (Binary::Dll(path) | Binary::Jar(path) | Binary::Exe(path)) = &self.bot_binary;
+        sanitize_character_name(
+            &path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy())
+                .unwrap_or_default(),
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum Binary {
+    /// AI module, loaded by BWAPI itself
     Dll(PathBuf),
+    /// Client bot, connects to BWAPI via the game table (see `Exe`)
     Jar(PathBuf),
+    /// Client bot: BWAPI runs as server without an AI module, the bot has to be started after
+    /// the server is waiting in the game table. It then connects to the server via shared memory.
     Exe(PathBuf),
 }
 
@@ -44,10 +122,66 @@ impl Binary {
             })
     }
 
+    /// Command for client bots, `None` for AI modules. `jvm_args` are only passed to Java.
+    pub fn client_command(
+        &self,
+        sandbox: &SandboxMode,
+        java_path: Option<&str>,
+        jvm_args: &[String],
+    ) -> Option<Command> {
+        match self {
+            Binary::Dll(_) => None,
+            Binary::Jar(jar) => {
+                let mut cmd = sandbox.wrap_executable(java_path.unwrap_or("java.exe"));
+                cmd.args(jvm_args).arg("-jar").arg(jar);
+                Some(cmd)
+            }
+            Binary::Exe(exe) => Some(sandbox.wrap_executable(exe)),
+        }
+    }
+
+    /// Extracts a zipped bot to `target` and finds its binary: In its `AI` folder (as BWAPI
+    /// would), or the only binary of the archive. Also returns the folder the bot is in, to run it
+    /// there.
+    pub(crate) fn unzip(zip: &Path, target: &Path) -> anyhow::Result<(PathBuf, Self)> {
+        info!(
+            "Unzipping '{}' to '{}'",
+            zip.to_string_lossy(),
+            target.to_string_lossy()
+        );
+        let archive = File::open(zip)
+            .with_context(|| format!("Could not open '{}'", zip.to_string_lossy()))?;
+        unzip(archive, target)
+            .with_context(|| format!("Could not unzip '{}'", zip.to_string_lossy()))?;
+        let mut root = target.to_path_buf();
+        // Archives often wrap everything in a folder named after the bot
+        let entries: Vec<_> = read_dir(&root)?.flatten().map(|it| it.path()).collect();
+        if let [folder] = entries.as_slice() {
+            if folder.is_dir() {
+                root = folder.clone();
+            }
+        }
+        let search_path = [root.join("bwapi-data").join("AI"), root.join("AI")]
+            .into_iter()
+            .find(|it| it.is_dir())
+            .unwrap_or_else(|| root.clone());
+        let binary = Self::search(&search_path)
+            .with_context(|| format!("Could not find the bot in '{}'", zip.to_string_lossy()))?;
+        Ok((root, binary))
+    }
+
     pub(crate) fn search(search_path: &Path) -> anyhow::Result<Self> {
         let mut executable = None;
         for file in read_dir(search_path)?.flatten() {
             let path = file.path();
+            // Zipped bots might bring their BWAPI
+            if path
+                .file_name()
+                .map(|name| name.eq_ignore_ascii_case("BWAPI.dll"))
+                .unwrap_or(false)
+            {
+                continue;
+            }
             if let Some(detected_binary) = Binary::from_path(&path) {
                 executable = Some(match (executable, detected_binary) {
                     (None, dll @ Binary::Dll(_)) | (Some(dll @ Binary::Dll(_)), Binary::Jar(_)) => {
@@ -69,3 +203,35 @@ impl Binary {
         }
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use std::path::PathBuf;
+
+    use crate::botsetup::{Binary, BotSetup};
+    use crate::sandbox::SandboxMode;
+    use crate::Race;
+
+    /// A DLL bot with a tournament module, playing as `player_name`
+    pub fn bot_setup(player_name: &str) -> BotSetup {
+        BotSetup {
+            starcraft_exe: PathBuf::from("StarCraft.exe"),
+            starcraft_path: PathBuf::new(),
+            player_name: player_name.to_string(),
+            bot_binary: Binary::Dll(PathBuf::from("bwapi-data/AI/ExampleAIModule.dll")),
+            debug_binary: None,
+            bot_base_path: PathBuf::from("bots/NitekatT"),
+            ini_dir: PathBuf::from("bots/NitekatT/bwapi-data"),
+            tournament_module: Some(PathBuf::from("TM_440.dll")),
+            replay_template: None,
+            replay_root: None,
+            race: Race::Terran,
+            observer: false,
+            game_speed: 0,
+            sound: false,
+            auto_restart: false,
+            sandbox: SandboxMode::NoSandbox,
+            own_bwapi_ini: None,
+        }
+    }
+}
@@ -1,108 +1,660 @@
 use crate::botsetup::BotSetup;
+use crate::map::is_map_file;
 use crate::{Binary, Race};
+use anyhow::Context;
+use crc::{Crc, CRC_32_ISO_HDLC};
+use log::{debug, trace, warn};
+use retry::{retry, OperationResult};
 use serde::de::Unexpected;
 use serde::{Deserialize, Deserializer, Serialize};
 use shared_memory::*;
 use std::fmt::{Display, Formatter};
 use std::io::Write;
 use std::mem::size_of;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use winapi::um::sysinfoapi::GetTickCount;
 
-#[derive(Debug, Eq, PartialEq)]
+/// Replays go to the bot folder, sorted by date
+pub const DEFAULT_REPLAY_TEMPLATE: &str =
+    "replays/$Y $b $d/%MAP%_%BOTRACE%%ALLYRACES%vs%ENEMYRACES%_$H$M$S.rep";
+
+/// BWAPI clients will ignore servers that did not send a keep alive within this many ms
+pub const MAX_KEEP_ALIVE_AGE: u32 = 60_000;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum BwapiVersion {
+    Bwapi374,
     Bwapi375,
     Bwapi412,
     Bwapi420,
     Bwapi440,
+    /// Not a known release, ie. a custom build - the ini layout should still work
+    Unknown(u32),
+}
+
+/// Accepted by `BwapiVersion::from_str`
+const BWAPI_VERSION_TOKENS: &str = "one of 374/375/412/420/440";
+
+impl FromStr for BwapiVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BwapiVersion::KNOWN
+            .iter()
+            .find(|version| version.version_short() == s.replace('.', ""))
+            .copied()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid BWAPI version '{}', expected {}",
+                    s,
+                    BWAPI_VERSION_TOKENS
+                )
+            })
+    }
+}
+
+impl<'d> Deserialize<'d> for BwapiVersion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'d>,
+    {
+        let version = String::deserialize(deserializer)?;
+        version.parse().map_err(|_| {
+            serde::de::Error::invalid_value(Unexpected::Str(&version), &BWAPI_VERSION_TOKENS)
+        })
+    }
+}
+
+/// CRCs of the released BWAPI.dll files
+const DLL_CRCS: &[(u32, BwapiVersion)] = &[
+    (0x71CB208B, BwapiVersion::Bwapi440),
+    (0xD1E0DDDF, BwapiVersion::Bwapi420),
+    (0x267BD0D5, BwapiVersion::Bwapi412),
+    (0x4E39C88A, BwapiVersion::Bwapi375),
+    (0x41128276, BwapiVersion::Bwapi374),
+];
+
+/// CRCs of the tournament modules in the `tm` folder
+const TM_CRCS: &[(u32, BwapiVersion)] = &[
+    (0x8A3F3155, BwapiVersion::Bwapi440),
+    (0xEDAD1367, BwapiVersion::Bwapi420),
+    (0xEC74EF3F, BwapiVersion::Bwapi412),
+    (0x54A44D2D, BwapiVersion::Bwapi375),
+];
+
+/// All released BWAPI versions were built for StarCraft 1.16.1
+const STARCRAFT_VERSION: [u16; 3] = [1, 16, 1];
+
+/// File version of a Windows executable (ie. `[1, 16, 1, 1]`), from its `VS_FIXEDFILEINFO`
+pub fn file_version(exe: &[u8]) -> Option<[u16; 4]> {
+    const SIGNATURE: [u8; 4] = 0xFEEF04BDu32.to_le_bytes();
+    let start = exe.windows(4).position(|it| it == SIGNATURE)?;
+    // Signature, struct version, then the file version as 2 DWORDs
+    let info = exe.get(start + 8..start + 16)?;
+    let dword =
+        |at: usize| u32::from_le_bytes([info[at], info[at + 1], info[at + 2], info[at + 3]]);
+    let (ms, ls) = (dword(0), dword(4));
+    Some([(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16])
+}
+
+fn version_of(crcs: &[(u32, BwapiVersion)], crc: u32) -> Option<BwapiVersion> {
+    crcs.iter()
+        .find(|(it, _)| *it == crc)
+        .map(|(_, version)| *version)
 }
 
 impl BwapiVersion {
+    pub const KNOWN: &'static [BwapiVersion] = &[
+        Self::Bwapi374,
+        Self::Bwapi375,
+        Self::Bwapi412,
+        Self::Bwapi420,
+        Self::Bwapi440,
+    ];
+
+    /// Only CRCs of released DLLs we have are known: Rebuilds like the 4.1.2 "Steam" build are
+    /// custom builds (see `from_u32_lenient`) until someone adds theirs
     pub fn from_u32(crc: u32) -> Option<BwapiVersion> {
-        match crc {
-            0x71CB208B => Some(Self::Bwapi440),
-            0xD1E0DDDF => Some(Self::Bwapi420),
-            0x267BD0D5 => Some(Self::Bwapi412),
-            0x4E39C88A => Some(Self::Bwapi375),
-            0x41128276 => Some(Self::Bwapi375),
-            _ => None,
+        version_of(DLL_CRCS, crc)
+    }
+
+    /// Like `from_u32`, but unknown CRCs are only logged instead of failing
+    pub fn from_u32_lenient(crc: u32) -> BwapiVersion {
+        Self::from_u32(crc).unwrap_or_else(|| {
+            warn!(
+                "Unknown BWAPI.dll (CRC 0x{:08X}), treating it as a custom build",
+                crc
+            );
+            Self::Unknown(crc)
+        })
+    }
+
+    /// Detects the version of a BWAPI.dll, `Ok(None)` if it could be read but is unknown
+    pub fn from_dll(path: &Path) -> std::io::Result<Option<BwapiVersion>> {
+        Ok(Self::from_u32(Self::dll_crc(path)?))
+    }
+
+    /// Like `from_dll`, but unknown versions are only logged (see `from_u32_lenient`)
+    pub fn from_dll_lenient(path: &Path) -> std::io::Result<BwapiVersion> {
+        match Self::from_dll(path)? {
+            Some(version) => Ok(version),
+            // Rare enough to not care about reading the dll twice
+            None => Ok(Self::from_u32_lenient(Self::dll_crc(path)?)),
+        }
+    }
+
+    /// Version of a tournament module in the `tm` folder by its CRC
+    pub fn from_tm_crc(crc: u32) -> Option<BwapiVersion> {
+        version_of(TM_CRCS, crc)
+    }
+
+    /// Prints every known version with the CRCs of its BWAPI.dll and tournament module
+    pub fn write_known(out: &mut impl Write) -> std::io::Result<()> {
+        let crcs = |crcs: &[(u32, BwapiVersion)], version| {
+            let crcs: Vec<_> = crcs
+                .iter()
+                .filter(|(_, it)| *it == version)
+                .map(|(crc, _)| format!("0x{:08X}", crc))
+                .collect();
+            if crcs.is_empty() {
+                "-".to_string()
+            } else {
+                crcs.join(", ")
+            }
+        };
+        writeln!(
+            out,
+            "{:8} {:12} {:12} TM version",
+            "BWAPI", "DLL CRC", "TM CRC"
+        )?;
+        for &version in Self::KNOWN {
+            writeln!(
+                out,
+                "{:8} {:12} {:12} {}",
+                version.version_short(),
+                crcs(DLL_CRCS, version),
+                crcs(TM_CRCS, version),
+                version.tm_version_short().unwrap_or("-")
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn dll_crc(path: &Path) -> std::io::Result<u32> {
+        Ok(Self::crc(std::fs::read(path)?.as_slice()))
+    }
+
+    /// CRC as used for BWAPI.dll and tournament modules, and for StarCraft.exe in messages
+    pub fn crc(bytes: &[u8]) -> u32 {
+        Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(bytes)
+    }
+
+    /// StarCraft this version of BWAPI was built for, `None` for unknown builds
+    pub fn starcraft_version(&self) -> Option<[u16; 3]> {
+        match self {
+            Self::Unknown(_) => None,
+            _ => Some(STARCRAFT_VERSION),
         }
     }
 
     pub fn version_short(&self) -> &'static str {
         match self {
+            Self::Bwapi374 => "374",
             Self::Bwapi375 => "375",
             Self::Bwapi412 => "412",
             Self::Bwapi420 => "420",
             Self::Bwapi440 => "440",
+            Self::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Version of the tournament module to use, `None` if there is none
+    pub fn tm_version_short(&self) -> Option<&'static str> {
+        match self {
+            // BWAPI 375 is a drop-in replacement for 374, so is its TM
+            Self::Bwapi374 => Some("375"),
+            Self::Unknown(_) => None,
+            _ => Some(self.version_short()),
         }
     }
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Serialize, Clone, Copy, Debug)]
 pub struct GameInstance {
     pub server_process_id: u32,
     pub is_connected: bool,
+    /// Set by the BWAPI server to `GetTickCount()`: ms since system start, wrapping after ~49.7 days
     pub last_keep_alive_time: u32,
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug)]
+impl GameInstance {
+    /// Reads an instance as laid out by BWAPI (`repr(C)`: pid, bool, padding, keep alive),
+    /// `None` if the bytes can't be one - ie. the table is still being initialized
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        // Anything but 0 or 1 isn't a bool
+        let is_connected = match bytes[4] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        Some(Self {
+            server_process_id: u32_at(0),
+            is_connected,
+            last_keep_alive_time: u32_at(8),
+        })
+    }
+}
+
+/// Current time of the clock used for `GameInstance::last_keep_alive_time`
+pub fn tick_count() -> u32 {
+    unsafe { GetTickCount() }
+}
+
+/// BWAPI's game table has 8 instances. The mapping is rounded up to a page, its length doesn't tell.
+const GAME_INSTANCES: usize = 8;
+
+/// Windows' error opening a file mapping that does not exist (yet)
+const ERROR_FILE_NOT_FOUND: u32 = 2;
+
+/// State of a slot in the game table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotState {
+    Empty,
+    /// A server waits for its client to connect
+    Waiting {
+        server_process_id: u32,
+    },
+    Connected {
+        server_process_id: u32,
+    },
+}
+
+impl From<&GameInstance> for SlotState {
+    fn from(instance: &GameInstance) -> Self {
+        match instance {
+            GameInstance {
+                server_process_id: 0,
+                ..
+            } => Self::Empty,
+            GameInstance {
+                server_process_id,
+                is_connected: false,
+                ..
+            } => Self::Waiting {
+                server_process_id: *server_process_id,
+            },
+            GameInstance {
+                server_process_id, ..
+            } => Self::Connected {
+                server_process_id: *server_process_id,
+            },
+        }
+    }
+}
+
+/// How long and how often to poll the game table while waiting for servers and clients
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct WaitConfig {
+    #[serde(rename = "poll_interval_ms", deserialize_with = "millis")]
+    pub poll_interval: Duration,
+    #[serde(rename = "max_wait_ms", deserialize_with = "millis")]
+    pub max_wait: Duration,
+    /// How long StarCraft may take to start BWAPI with a DLL bot
+    #[serde(rename = "inject_timeout_ms", deserialize_with = "millis")]
+    pub inject_timeout: Duration,
+    /// First delay between attempts to open the game table, doubled up to `open_backoff_max`
+    #[serde(rename = "open_backoff_base_ms", deserialize_with = "millis")]
+    pub open_backoff_base: Duration,
+    #[serde(rename = "open_backoff_max_ms", deserialize_with = "millis")]
+    pub open_backoff_max: Duration,
+    /// How long the first BWAPI may take to create the game table
+    #[serde(rename = "open_timeout_ms", deserialize_with = "millis")]
+    pub open_timeout: Duration,
+    /// How long bots may take from the launch of the first one until the game starts, 0 to wait
+    /// forever. Only bots with a tournament module tell when the game started.
+    #[serde(rename = "lobby_timeout_ms", deserialize_with = "millis")]
+    pub lobby_timeout: Duration,
+    /// Hold the game until every bot is ready: Client bots may take until the lobby timeout to
+    /// connect (instead of `max_wait`, forever without a lobby timeout or with a human host), and
+    /// the host waits for all players - observers included
+    pub wait_all_ready: bool,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(100),
+            max_wait: Duration::from_secs(10),
+            inject_timeout: Duration::from_secs(30),
+            open_backoff_base: Duration::from_millis(50),
+            open_backoff_max: Duration::from_secs(2),
+            open_timeout: Duration::from_secs(30),
+            lobby_timeout: Duration::from_secs(180),
+            wait_all_ready: false,
+        }
+    }
+}
+
+impl WaitConfig {
+    /// Delays for `retry`, ending once `max_wait` passed
+    pub fn delays(&self) -> impl Iterator<Item = Duration> {
+        self.delays_for(self.max_wait)
+    }
+
+    /// Delays for `retry`, ending once `inject_timeout` passed
+    pub fn inject_delays(&self) -> impl Iterator<Item = Duration> {
+        self.delays_for(self.inject_timeout)
+    }
+
+    /// Exponentially growing delays for `retry`, ending once `open_timeout` passed
+    pub fn open_delays(&self) -> impl Iterator<Item = Duration> {
+        let deadline = Instant::now() + self.open_timeout;
+        let max = self.open_backoff_max;
+        std::iter::successors(Some(self.open_backoff_base.min(max)), move |delay| {
+            Some((*delay * 2).min(max))
+        })
+        .take_while(move |_| Instant::now() < deadline)
+    }
+
+    fn delays_for(&self, max_wait: Duration) -> impl Iterator<Item = Duration> {
+        self.delays_until(Some(Instant::now() + max_wait))
+    }
+
+    /// Delays for `retry`, ending at `deadline` - never without one
+    pub fn delays_until(&self, deadline: Option<Instant>) -> impl Iterator<Item = Duration> {
+        retry::delay::Fixed::from(self.poll_interval)
+            .take_while(move |_| !matches!(deadline, Some(deadline) if Instant::now() >= deadline))
+    }
+}
+
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+#[derive(Clone, Debug)]
 pub struct GameTable {
-    pub game_instances: [GameInstance; 8],
+    pub game_instances: Vec<GameInstance>,
+}
+
+impl GameTable {
+    /// The instances at the start of `bytes` (the rest of the page is padding), `None` if any of
+    /// them is garbage (see `GameInstance::from_bytes`)
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let len = bytes.len().min(size_of::<GameInstance>() * GAME_INSTANCES);
+        Some(Self {
+            game_instances: bytes[..len]
+                .chunks_exact(size_of::<GameInstance>())
+                .map(GameInstance::from_bytes)
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    pub fn stale_slots(&self, now: u32, max_age: u32) -> Vec<usize> {
+        self.game_instances
+            .iter()
+            .enumerate()
+            .filter(|(_, it)| {
+                it.server_process_id != 0 && now.wrapping_sub(it.last_keep_alive_time) > max_age
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Processes of the servers that are still alive, stale slots of crashed servers don't count
+    pub fn live_pids(&self, now: u32) -> Vec<u32> {
+        let stale = self.stale_slots(now, MAX_KEEP_ALIVE_AGE);
+        self.game_instances
+            .iter()
+            .enumerate()
+            .filter(|(i, it)| it.server_process_id != 0 && !stale.contains(i))
+            .map(|(_, it)| it.server_process_id)
+            .collect()
+    }
+
+    /// Slots of the BWAPI servers `pids` a client bot connected to
+    pub fn connected_count(&self, pids: &[u32]) -> usize {
+        self.game_instances
+            .iter()
+            .filter(|it| {
+                it.server_process_id != 0 && it.is_connected && pids.contains(&it.server_process_id)
+            })
+            .count()
+    }
+}
+
+/// Slots of BWAPI's game table not taken by a live server, ie. for a scheduler to decide how many
+/// games to start. Doesn't need a `GameConfig` or a `Shotgun`, and doesn't keep the table open.
+/// Without a game table no BWAPI runs, all 8 slots are free. Fails if there is one, but
+/// it can't be read.
+pub fn free_slot_count() -> std::io::Result<usize> {
+    GameTableAccess::new()
+        .free_slot_count()
+        .map_err(std::io::Error::other)
+}
+
+/// Processes of all live BWAPI servers in the game table, ie. StarCraft (see `free_slot_count`)
+pub fn occupied_pids() -> std::io::Result<Vec<u32>> {
+    GameTableAccess::new()
+        .occupied_pids()
+        .map_err(std::io::Error::other)
 }
 
+/// Reads BWAPI's game table. The shared memory is opened on first use and stays open until
+/// `close` or drop, StarCraft keeps its own handle. Only stale slots are ever written to, there's
+/// nothing to release besides the handle. Windows frees the game table once the last handle is
+/// closed - long running programs should close it between games, or a table full of stale slots
+/// outlives all StarCraft instances.
 pub struct GameTableAccess {
+    os_id: String,
     game_table: Option<Shmem>,
 }
 
+impl Drop for GameTableAccess {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
 impl GameTableAccess {
     pub fn new() -> Self {
-        Self { game_table: None }
+        Self::with_os_id(r"Local\bwapi_shared_memory_game_list")
+    }
+
+    /// Use another name for the shared memory, ie. `Global\bwapi_shared_memory_game_list` for services
+    pub fn with_os_id(os_id: impl Into<String>) -> Self {
+        Self {
+            os_id: os_id.into(),
+            game_table: None,
+        }
+    }
+
+    pub fn os_id(&self) -> &str {
+        &self.os_id
+    }
+
+    /// Closes the shared memory, the next access opens it again
+    pub fn close(&mut self) {
+        if self.game_table.take().is_some() {
+            debug!("Closed the game table");
+        }
     }
 
-    pub fn get_game_table(&mut self) -> Option<GameTable> {
+    /// The current game table, `Ok(None)` if no BWAPI server created (and initialized) it yet
+    pub fn get_game_table(&mut self) -> Result<Option<GameTable>, ShmemError> {
         if self.game_table.is_none() {
             let shmmem = ShmemConf::new()
-                .size(size_of::<GameTable>())
+                .size(size_of::<GameInstance>() * GAME_INSTANCES)
                 .allow_raw(true)
-                .os_id(r"Local\bwapi_shared_memory_game_list")
+                .os_id(&self.os_id)
                 .open();
-            self.game_table = shmmem.ok();
+            self.game_table = match shmmem {
+                Ok(shmem) => Some(shmem),
+                Err(
+                    ShmemError::MapOpenFailed(ERROR_FILE_NOT_FOUND) | ShmemError::LinkDoesNotExist,
+                ) => None,
+                Err(e) => return Err(e),
+            };
+        }
+        Ok(self.game_table.as_ref().and_then(|shmem| {
+            let bytes = unsafe { std::slice::from_raw_parts(shmem.as_ptr(), shmem.len()) };
+            let table = GameTable::from_bytes(bytes);
+            if table.is_none() {
+                debug!("The game table is not initialized yet");
+            }
+            table
+        }))
+    }
+
+    /// Waits for a BWAPI server to create the game table (see `WaitConfig::open_delays`), `gave_up`
+    /// tells why to stop early (ie. StarCraft died)
+    pub fn wait_for_game_table(
+        &mut self,
+        wait: &WaitConfig,
+        gave_up: impl FnMut() -> Option<&'static str>,
+    ) -> anyhow::Result<()> {
+        let opened = self.poll(
+            wait.open_delays(),
+            "BWAPI never created the game table",
+            gave_up,
+            |access| Ok(access.get_game_table()?.is_some()),
+        )?;
+        opened.map_err(|e| anyhow::anyhow!("{} ('{}')", e, self.os_id))
+    }
+
+    /// Polls `ready` until it's true, failing with `not_ready` once `delays` ran out, or with the
+    /// reason `gave_up` tells (ie. StarCraft died). The outer `Err` is for a game table that can't
+    /// be read, retrying won't help with that.
+    pub fn poll(
+        &mut self,
+        delays: impl IntoIterator<Item = Duration>,
+        not_ready: &'static str,
+        mut gave_up: impl FnMut() -> Option<&'static str>,
+        mut ready: impl FnMut(&mut Self) -> Result<bool, ShmemError>,
+    ) -> anyhow::Result<Result<(), retry::Error<&'static str>>> {
+        let mut table_error = None;
+        let polled = retry(delays, || {
+            if let Some(reason) = gave_up() {
+                return OperationResult::Err(reason);
+            }
+            match ready(self) {
+                Ok(true) => OperationResult::Ok(()),
+                Ok(false) => OperationResult::Retry(not_ready),
+                Err(e) => {
+                    table_error = Some(e);
+                    OperationResult::Err("Could not read the game table")
+                }
+            }
+        });
+        match table_error {
+            Some(e) => Err(e).context("Could not read the game table"),
+            None => Ok(polled),
         }
-        self.game_table
-            .as_ref()
-            .map(|shmem| unsafe { *(shmem.as_ptr() as *const GameTable) })
     }
 
-    pub fn all_slots_filled(&mut self) -> bool {
+    /// Indices of occupied slots with a `last_keep_alive_time` older than `max_age` ms,
+    /// `now` has to be taken from the same clock (see `tick_count`)
+    pub fn stale_slots(&mut self, now: u32, max_age: u32) -> Vec<usize> {
         self.get_game_table()
+            .ok()
+            .flatten()
+            .map(|table| table.stale_slots(now, max_age))
+            .unwrap_or_default()
+    }
+
+    /// Frees all slots left behind by crashed servers, so they won't block waiting for slots
+    pub fn clear_stale_slots(&mut self) {
+        for i in self.stale_slots(tick_count(), MAX_KEEP_ALIVE_AGE) {
+            if let Some(shmem) = &self.game_table {
+                let instance = unsafe { &mut *(shmem.as_ptr() as *mut GameInstance).add(i) };
+                warn!(
+                    "Clearing stale slot {} of process {}",
+                    i, instance.server_process_id
+                );
+                instance.server_process_id = 0;
+                instance.is_connected = false;
+            }
+        }
+    }
+
+    /// At least `n` slots of the BWAPI servers `pids` (ie. of one game) are connected - servers
+    /// of other games or phantom slots don't count
+    pub fn expected_connected(&mut self, pids: &[u32], n: usize) -> Result<bool, ShmemError> {
+        Ok(self
+            .get_game_table()?
             .map(|table| {
-                // eprintln!("{:#?}", table);
-                !table
-                    .game_instances
-                    .iter()
-                    .any(|it| it.server_process_id != 0 && !it.is_connected)
+                trace!("{:?}", table);
+                table.connected_count(pids) >= n
             })
-            .unwrap_or(false)
+            .unwrap_or(false))
+    }
+
+    /// Processes of all live BWAPI servers in the game table, ie. StarCraft. Stale slots of
+    /// crashed servers don't count.
+    pub fn occupied_pids(&mut self) -> Result<Vec<u32>, ShmemError> {
+        Ok(self
+            .get_game_table()?
+            .map(|table| table.live_pids(tick_count()))
+            .unwrap_or_default())
+    }
+
+    /// Number of slots in the game table, BWAPI creates it with `GAME_INSTANCES` slots
+    pub fn slot_count(&mut self) -> Result<usize, ShmemError> {
+        Ok(self
+            .get_game_table()?
+            .map(|table| table.game_instances.len())
+            .unwrap_or(GAME_INSTANCES))
+    }
+
+    /// Slots not taken by a live BWAPI server, all of them without a game table
+    pub fn free_slot_count(&mut self) -> Result<usize, ShmemError> {
+        Ok(self
+            .get_game_table()?
+            .map(|table| table.game_instances.len() - table.live_pids(tick_count()).len())
+            .unwrap_or(GAME_INSTANCES))
     }
 
-    pub fn has_free_slot(&mut self) -> bool {
+    /// Every slot as BWAPI wrote it, empty if there is no game table (yet)
+    pub fn instances(&mut self) -> Vec<GameInstance> {
         self.get_game_table()
+            .ok()
+            .flatten()
+            .map(|table| table.game_instances)
+            .unwrap_or_default()
+    }
+
+    /// State of every slot, empty if there is no game table (yet)
+    pub fn snapshot(&mut self) -> Vec<SlotState> {
+        self.instances().iter().map(SlotState::from).collect()
+    }
+
+    pub fn has_free_slot(&mut self) -> Result<bool, ShmemError> {
+        Ok(self
+            .get_game_table()?
             .map(|table| {
+                trace!("{:?}", table);
                 table
                     .game_instances
                     .iter()
                     .any(|it| it.server_process_id != 0 && !it.is_connected)
             })
-            .unwrap_or(false)
+            .unwrap_or(false))
     }
 }
 
+#[derive(Debug)]
 pub enum BwapiConnectMode {
     Host {
         map: Option<String>,
-        player_count: usize,
+        /// The game starts once this many players joined (after BWAPI's `wait_for_time`)
+        min_players: usize,
+        /// ... or right away once this many joined
+        max_players: usize,
     },
     Join,
 }
@@ -142,16 +694,117 @@ impl Display for BwapiLanMode {
     }
 }
 
+/// BWAPI's `speed_override` by name, or in milliseconds per frame for anything else
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameSpeed {
+    /// 42ms per frame, StarCraft's "Fastest" - what ladders play with
+    Fastest,
+    /// 56ms per frame
+    Fast,
+    /// 67ms per frame
+    Normal,
+    /// 83ms per frame
+    Slow,
+    /// -1, no override: The speed selected in StarCraft (or by a bot)
+    Human,
+    /// 0 for as fast as possible
+    Millis(i32),
+}
+
+impl GameSpeed {
+    pub fn to_override(&self) -> i32 {
+        match self {
+            GameSpeed::Fastest => 42,
+            GameSpeed::Fast => 56,
+            GameSpeed::Normal => 67,
+            GameSpeed::Slow => 83,
+            GameSpeed::Human => -1,
+            GameSpeed::Millis(millis) => *millis,
+        }
+    }
+}
+
+/// Accepted by `GameSpeed::from_str`, case-insensitive
+const GAME_SPEED_TOKENS: &str = "one of fastest/fast/normal/slow/human or ms per frame";
+
+impl FromStr for GameSpeed {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fastest" => Ok(GameSpeed::Fastest),
+            "fast" => Ok(GameSpeed::Fast),
+            "normal" => Ok(GameSpeed::Normal),
+            "slow" => Ok(GameSpeed::Slow),
+            "human" => Ok(GameSpeed::Human),
+            x => x.parse().map(GameSpeed::Millis).map_err(|_| {
+                anyhow::anyhow!("Invalid game speed '{}', expected {}", s, GAME_SPEED_TOKENS)
+            }),
+        }
+    }
+}
+
+impl<'d> Deserialize<'d> for GameSpeed {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'d>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum NameOrMillis {
+            Millis(i32),
+            Name(String),
+        }
+        match NameOrMillis::deserialize(deserializer)? {
+            NameOrMillis::Millis(millis) => Ok(GameSpeed::Millis(millis)),
+            NameOrMillis::Name(name) => name.parse().map_err(|_| {
+                serde::de::Error::invalid_value(Unexpected::Str(&name), &GAME_SPEED_TOKENS)
+            }),
+        }
+    }
+}
+
+/// Brood War does not support longer character names
+pub const MAX_CHARACTER_NAME_LEN: usize = 24;
+
+/// Brood War's lobby does not support longer game names
+pub const MAX_GAME_NAME_LEN: usize = 24;
+
+/// Removes characters Brood War can't display (anything but printable ASCII) and truncates the
+/// name to `MAX_CHARACTER_NAME_LEN`
+pub fn sanitize_character_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .filter(|c| c.is_ascii_graphic() || *c == ' ')
+        .collect();
+    sanitized.truncate(MAX_CHARACTER_NAME_LEN);
+    sanitized.trim().to_string()
+}
+
+#[derive(Debug)]
 pub enum AutoMenu {
     // Managed by bwheadless
     Unused,
     // Managed by BWAPI + injectory
-    AutoMenu {
+    Lan {
+        /// Character name, required to join as well as to host (LAN games are named after their host)
         name: String,
-        race: Race,
+        /// `None` for observers, BWAPI joins as Random then
+        race: Option<Race>,
         game_name: String,
         connect_mode: BwapiConnectMode,
         lan_mode: BwapiLanMode,
+        /// Start the next game once a game ended, ie. to rejoin after a disconnect
+        auto_restart: bool,
+    },
+    // Managed by BWAPI + injectory, a melee against the computer without any LAN game
+    SinglePlayer {
+        name: String,
+        race: Race,
+        map: String,
+        /// Race of each computer player, none leaves it to BWAPI (one computer of the map's default race).
+        /// There is no difficulty to pick, Brood War's melee AI always plays the same.
+        computer_races: Vec<Race>,
     },
 }
 
@@ -161,66 +814,296 @@ impl Default for AutoMenu {
     }
 }
 
+/// The `map` as BWAPI reads it with `GetPrivateProfileStringA`: Spaces inside are kept, but
+/// surrounding ones are trimmed unless the whole value is quoted (the quotes are removed).
+fn map_value(map: &str) -> std::io::Result<String> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+    // BWAPI would just sit in the menu with anything else
+    if !is_map_file(Path::new(map)) {
+        return Err(invalid(format!("'{}' is not a Brood War map", map)));
+    }
+    // Read in the ANSI code page of the system, which we can't write reliably
+    if !map.is_ascii() {
+        return Err(invalid(format!(
+            "BWAPI can't read the map '{}', please rename it (or its folders) to only use ASCII characters",
+            map
+        )));
+    }
+    // Maps end with their extension, so they can't end with a quote as well
+    Ok(if map.trim() != map {
+        format!("\"{}\"", map)
+    } else {
+        map.to_string()
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self::CrLf
+        } else {
+            Self::Lf
+        }
+    }
+}
+
+impl LineEnding {
+    /// Writes `ini` (with `\n` line endings) with these line endings
+    fn write(&self, ini: &[u8], out: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            LineEnding::Lf => out.write_all(ini),
+            LineEnding::CrLf => {
+                for line in ini.split_inclusive(|&b| b == b'\n') {
+                    match line.strip_suffix(b"\n") {
+                        Some(line) => {
+                            out.write_all(line)?;
+                            out.write_all(b"\r\n")?;
+                        }
+                        None => out.write_all(line)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Lines of an ini file by section (lower case, `""` for lines before the first section), in order
+fn ini_sections(ini: &str) -> Vec<(String, Vec<&str>)> {
+    let mut sections = vec![(String::new(), vec![])];
+    for line in ini.lines() {
+        let trimmed = line.trim();
+        match trimmed
+            .strip_prefix('[')
+            .and_then(|it| it.strip_suffix(']'))
+        {
+            Some(section) => sections.push((section.trim().to_lowercase(), vec![line])),
+            None => sections.last_mut().expect("Always a section").1.push(line),
+        }
+    }
+    sections
+}
+
+fn ini_section<'a, 'b>(
+    sections: &'b [(String, Vec<&'a str>)],
+    name: &str,
+) -> Option<&'b [&'a str]> {
+    sections
+        .iter()
+        .find(|(section, _)| section == name)
+        .map(|(_, lines)| lines.as_slice())
+}
+
+/// The key of an ini line, `None` for section headers, comments and empty lines
+fn ini_key(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.starts_with([';', '#', '[']) {
+        return None;
+    }
+    line.split_once('=')
+        .map(|(key, _)| key.trim().to_lowercase())
+}
+
 /// Although BWAPI can manage multiple bots with one BWAPI.ini, we'll be using one per bot
-#[derive(Default)]
+#[derive(Default, Debug)]
 pub struct BwapiIni {
     pub ai_module: String,
+    /// Loaded instead of `ai_module` by debug builds of BWAPI
+    pub ai_module_debug: Option<String>,
     pub tm_module: Option<PathBuf>,
     // default: 0 - full throttle
     pub game_speed: i32,
     pub sound: bool,
     pub auto_menu: AutoMenu,
+    /// Written as is to `save_replay`, `None` disables saving replays
+    pub replay_template: Option<String>,
+    /// Prefixed to `replay_template`
+    pub replay_root: Option<PathBuf>,
+    /// Some older BWAPI versions drop the last key of a section with `\n` line endings
+    pub line_ending: LineEnding,
+    /// BWAPI's windowed mode with this size, StarCraft's own resolution if `None`
+    pub window_size: Option<(u32, u32)>,
 }
 
 impl BwapiIni {
     pub fn from(bot_setup: &BotSetup) -> Self {
         Self {
             ai_module: match &bot_setup.bot_binary {
-                Binary::Dll(x) => x.to_string_lossy().to_string(),
+                Binary::Dll(x) => bot_setup.resolve(x).to_string_lossy().to_string(),
                 Binary::Exe(_) | Binary::Jar(_) => "".to_string(),
             },
-            tm_module: bot_setup.tournament_module.clone(),
+            ai_module_debug: bot_setup
+                .debug_binary
+                .as_deref()
+                .map(|dll| bot_setup.resolve(dll).to_string_lossy().to_string()),
+            tm_module: bot_setup
+                .tournament_module
+                .as_deref()
+                .map(|tm| bot_setup.resolve(tm)),
+            replay_template: bot_setup.replay_template.clone(),
+            replay_root: bot_setup.replay_root.clone(),
+            game_speed: bot_setup.game_speed,
+            sound: bot_setup.sound,
             ..Default::default()
         }
     }
     pub fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let mut ini = vec![];
+        self.write_lines(&mut ini)?;
+        self.line_ending.write(&ini, out)
+    }
+
+    /// Like `write`, merged into the bot's own BWAPI.ini (see `merge_into`)
+    pub fn write_merged(&self, existing: &str, out: &mut impl Write) -> std::io::Result<()> {
+        self.line_ending
+            .write(self.merge_into(existing)?.as_bytes(), out)
+    }
+
+    /// Merges this BWAPI.ini into `existing` (with `\n` line endings): `[ai]` and `[auto_menu]`
+    /// are replaced, other sections keep their keys and only get the ones they are missing
+    pub fn merge_into(&self, existing: &str) -> std::io::Result<String> {
+        const MANAGED: [&str; 2] = ["ai", "auto_menu"];
+        let mut ini = vec![];
+        self.write_lines(&mut ini)?;
+        let ini = String::from_utf8_lossy(&ini);
+        let ours = ini_sections(&ini);
+        let theirs = ini_sections(existing);
+        let mut merged: Vec<&str> = vec![];
+        for (section, lines) in &theirs {
+            let our_lines = ini_section(&ours, section).unwrap_or_default();
+            if MANAGED.contains(&section.as_str()) {
+                merged.extend(our_lines);
+                continue;
+            }
+            let keys: Vec<_> = lines.iter().filter_map(|line| ini_key(line)).collect();
+            merged.extend(lines);
+            merged.extend(
+                our_lines
+                    .iter()
+                    .filter(|line| matches!(ini_key(line), Some(key) if !keys.contains(&key))),
+            );
+        }
+        for (section, lines) in &ours {
+            if ini_section(&theirs, section).is_none() {
+                merged.extend(lines);
+            }
+        }
+        Ok(merged.iter().map(|line| format!("{}\n", line)).collect())
+    }
+
+    fn write_lines(&self, out: &mut impl Write) -> std::io::Result<()> {
         writeln!(out, "[ai]")?;
         writeln!(out, "ai = {}", self.ai_module)?;
+        if let Some(ai_module_debug) = &self.ai_module_debug {
+            writeln!(out, "ai_dbg = {}", ai_module_debug)?;
+        }
         if let Some(tm) = &self.tm_module {
             writeln!(out, "tournament = {}", tm.to_string_lossy())?;
         }
         writeln!(out, "[auto_menu]")?;
         match &self.auto_menu {
             AutoMenu::Unused => (),
-            AutoMenu::AutoMenu {
+            AutoMenu::Lan {
                 name,
                 race,
                 game_name,
                 connect_mode,
                 lan_mode,
+                auto_restart,
             } => {
+                if name.trim().is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "A character name is required to host or join a game",
+                    ));
+                }
                 writeln!(out, "auto_menu=LAN")?;
                 writeln!(out, "lan_mode={}", lan_mode)?;
                 writeln!(out, "character_name={}", name)?;
-                writeln!(out, "race={}", race)?;
+                if let Some(race) = race {
+                    writeln!(out, "race={}", race)?;
+                }
+                if *auto_restart {
+                    writeln!(out, "auto_restart=ON")?;
+                }
                 match connect_mode {
-                    BwapiConnectMode::Host { map, player_count } => {
-                        if let Some(map_name) = map {
-                            writeln!(out, "map={}", map_name)?;
+                    BwapiConnectMode::Host {
+                        map,
+                        min_players,
+                        max_players,
+                    } => {
+                        let map_name = map.as_deref().ok_or_else(|| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidInput,
+                                "A map is required to host a game",
+                            )
+                        })?;
+
+                        if *min_players == 0 || min_players > max_players {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::InvalidInput,
+                                format!(
+                                    "Can't wait for {} to {} players",
+                                    min_players, max_players
+                                ),
+                            ));
                         }
-                        writeln!(out, "wait_for_min_players={}", player_count)?;
-                        writeln!(out, "wait_for_max_players={}", player_count)?;
+                        writeln!(out, "map={}", map_value(map_name)?)?;
+                        writeln!(out, "wait_for_min_players={}", min_players)?;
+                        writeln!(out, "wait_for_max_players={}", max_players)?;
                     }
                     BwapiConnectMode::Join => {
                         writeln!(out, "game={}", game_name)?;
                     }
                 }
             }
+            AutoMenu::SinglePlayer {
+                name,
+                race,
+                map,
+                computer_races,
+            } => {
+                if name.trim().is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "A character name is required to start a game",
+                    ));
+                }
+                writeln!(out, "auto_menu=SINGLE_PLAYER")?;
+                writeln!(out, "character_name={}", name)?;
+                writeln!(out, "race={}", race)?;
+                writeln!(out, "map={}", map_value(map)?)?;
+                writeln!(out, "game_type=MELEE")?;
+                if !computer_races.is_empty() {
+                    writeln!(out, "enemy_count={}", computer_races.len())?;
+                    for (slot, race) in computer_races.iter().enumerate() {
+                        writeln!(out, "enemy_race_{}={}", slot + 1, race)?;
+                    }
+                }
+            }
+        }
+        if let Some(replay_template) = &self.replay_template {
+            match &self.replay_root {
+                Some(replay_root) => writeln!(
+                    out,
+                    "save_replay = {}",
+                    replay_root.join(replay_template).to_string_lossy()
+                )?,
+                None => writeln!(out, "save_replay = {}", replay_template)?,
+            }
+        }
+        if let Some((width, height)) = self.window_size {
+            writeln!(out, "[window]")?;
+            writeln!(out, "windowed = ON")?;
+            writeln!(out, "width = {}", width)?;
+            writeln!(out, "height = {}", height)?;
         }
-        writeln!(
-            out,
-            "save_replay = replays/$Y $b $d/%MAP%_%BOTRACE%%ALLYRACES%vs%ENEMYRACES%_$H$M$S.rep"
-        )?;
         writeln!(out, "[starcraft]")?;
         writeln!(out, "speed_override = {}", self.game_speed)?;
         let sound = if self.sound { "ON" } else { "OFF" };
@@ -230,9 +1113,606 @@ impl BwapiIni {
 
 #[cfg(test)]
 mod test {
-    use crate::bwapi::BwapiVersion;
-    use crate::bwapi::BwapiVersion::{Bwapi375, Bwapi412, Bwapi420, Bwapi440};
+    use crate::botsetup::test::bot_setup;
+    use crate::botsetup::Binary;
+    use crate::bwapi::BwapiVersion::{Bwapi374, Bwapi375, Bwapi412, Bwapi420, Bwapi440};
+    use crate::bwapi::{
+        file_version, map_value, sanitize_character_name, AutoMenu, BwapiConnectMode, BwapiIni,
+        BwapiLanMode, BwapiVersion, GameInstance, GameSpeed, GameTable, GameTableAccess,
+        LineEnding, SlotState, WaitConfig, DEFAULT_REPLAY_TEMPLATE, GAME_INSTANCES,
+    };
+    use crate::{GameConfig, Race};
     use crc::{Crc, CRC_32_ISO_HDLC};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    fn ini_string(ini: &BwapiIni) -> String {
+        let mut out = vec![];
+        ini.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_random_race() {
+        let ini = BwapiIni {
+            auto_menu: AutoMenu::Lan {
+                name: "Bot".to_string(),
+                race: Some(Race::Random),
+                game_name: "shotgun".to_string(),
+                connect_mode: BwapiConnectMode::Join,
+                lan_mode: BwapiLanMode::LocalPC,
+                auto_restart: false,
+            },
+            ..Default::default()
+        };
+        assert!(ini_string(&ini).lines().any(|line| line == "race=Random"));
+    }
+
+    #[test]
+    fn test_auto_menu_races() {
+        // BWAPI falls back to Random for anything it doesn't know
+        for (race, token) in [
+            (Race::Terran, "Terran"),
+            (Race::Zerg, "Zerg"),
+            (Race::Protoss, "Protoss"),
+            (Race::Random, "Random"),
+        ] {
+            let ini = |connect_mode| BwapiIni {
+                auto_menu: AutoMenu::Lan {
+                    name: "Bot".to_string(),
+                    race: Some(race),
+                    game_name: "shotgun".to_string(),
+                    connect_mode,
+                    lan_mode: BwapiLanMode::LocalPC,
+                    auto_restart: false,
+                },
+                line_ending: LineEnding::Lf,
+                ..Default::default()
+            };
+            let auto_menu = |ini: &BwapiIni| {
+                let ini = ini_string(ini);
+                let start = ini.find("[auto_menu]\n").unwrap();
+                let end = ini[start + 1..]
+                    .find("\n[")
+                    .map(|end| start + end + 2)
+                    .unwrap_or(ini.len());
+                ini[start..end].to_string()
+            };
+            assert_eq!(
+                auto_menu(&ini(BwapiConnectMode::Host {
+                    map: Some("maps/(2)Destination.scx".to_string()),
+                    min_players: 2,
+                    max_players: 2,
+                })),
+                format!(
+                    "[auto_menu]\n\
+                    auto_menu=LAN\n\
+                    lan_mode=Local PC\n\
+                    character_name=Bot\n\
+                    race={}\n\
+                    map=maps/(2)Destination.scx\n\
+                    wait_for_min_players=2\n\
+                    wait_for_max_players=2\n",
+                    token
+                )
+            );
+            assert_eq!(
+                auto_menu(&ini(BwapiConnectMode::Join)),
+                format!(
+                    "[auto_menu]\n\
+                    auto_menu=LAN\n\
+                    lan_mode=Local PC\n\
+                    character_name=Bot\n\
+                    race={}\n\
+                    game=shotgun\n",
+                    token
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_auto_menu_observer() {
+        let ini = BwapiIni {
+            auto_menu: AutoMenu::Lan {
+                name: "Referee".to_string(),
+                race: None,
+                game_name: "shotgun".to_string(),
+                connect_mode: BwapiConnectMode::Join,
+                lan_mode: BwapiLanMode::LocalPC,
+                auto_restart: false,
+            },
+            line_ending: LineEnding::Lf,
+            ..Default::default()
+        };
+        let ini = ini_string(&ini);
+        assert!(ini.contains("character_name=Referee\ngame=shotgun\n"));
+        assert!(!ini.contains("race="));
+    }
+
+    #[test]
+    fn test_auto_menu_single_player() {
+        let ini = |map: &str| BwapiIni {
+            auto_menu: AutoMenu::SinglePlayer {
+                name: "Bot".to_string(),
+                race: Race::Zerg,
+                map: map.to_string(),
+                computer_races: vec![],
+            },
+            line_ending: LineEnding::Lf,
+            ..Default::default()
+        };
+        let written = ini_string(&ini("maps/(2)Destination.scx"));
+        assert!(written.contains(
+            "[auto_menu]\n\
+            auto_menu=SINGLE_PLAYER\n\
+            character_name=Bot\n\
+            race=Zerg\n\
+            map=maps/(2)Destination.scx\n\
+            game_type=MELEE\n"
+        ));
+        for lan_key in [
+            "lan_mode",
+            "wait_for_min_players",
+            "wait_for_max_players",
+            "game=",
+        ] {
+            assert!(!written.contains(lan_key), "{}", lan_key);
+        }
+        assert!(ini("maps/readme.txt").write(&mut vec![]).is_err());
+
+        let mut computers = ini("maps/(4)Python.scx");
+        if let AutoMenu::SinglePlayer { computer_races, .. } = &mut computers.auto_menu {
+            *computer_races = vec![Race::Terran, Race::Protoss];
+        }
+        assert!(ini_string(&computers).contains(
+            "game_type=MELEE\n\
+            enemy_count=2\n\
+            enemy_race_1=Terran\n\
+            enemy_race_2=Protoss\n"
+        ));
+    }
+
+    #[test]
+    fn test_game_speed() {
+        for (name, speed_override) in [
+            ("fastest", 42),
+            ("fast", 56),
+            ("Normal", 67),
+            ("slow", 83),
+            ("human", -1),
+            ("0", 0),
+            ("20", 20),
+        ] {
+            let game_speed: GameSpeed = name.parse().unwrap();
+            assert_eq!(game_speed.to_override(), speed_override, "{}", name);
+            let ini = BwapiIni {
+                game_speed: game_speed.to_override(),
+                ..Default::default()
+            };
+            let expected = format!("speed_override = {}", speed_override);
+            assert!(ini_string(&ini).lines().any(|line| line == expected));
+        }
+        assert!("ludicrous".parse::<GameSpeed>().is_err());
+
+        let config = |game_speed: &str| {
+            toml::from_str::<GameConfig>(&format!(
+                "game_type = {{ Melee = [] }}\ngame_speed = {}",
+                game_speed
+            ))
+            .map(|config| config.game_speed)
+        };
+        assert_eq!(config("'fastest'").unwrap(), Some(GameSpeed::Fastest));
+        assert_eq!(config("42").unwrap(), Some(GameSpeed::Millis(42)));
+        assert!(config("'ludicrous'").is_err());
+    }
+
+    #[test]
+    fn test_auto_restart() {
+        let ini = |auto_restart| BwapiIni {
+            auto_menu: AutoMenu::Lan {
+                name: "Bot".to_string(),
+                race: Some(Race::Zerg),
+                game_name: "shotgun".to_string(),
+                connect_mode: BwapiConnectMode::Join,
+                lan_mode: BwapiLanMode::LocalPC,
+                auto_restart,
+            },
+            ..Default::default()
+        };
+        assert!(ini_string(&ini(true))
+            .lines()
+            .any(|line| line == "auto_restart=ON"));
+        assert!(!ini_string(&ini(false)).contains("auto_restart"));
+    }
+
+    #[test]
+    fn test_replay_template() {
+        let ini = BwapiIni {
+            replay_template: Some("replays/match_17/%MAP%_$H$M$S.rep".to_string()),
+            ..Default::default()
+        };
+        assert!(ini_string(&ini)
+            .lines()
+            .any(|line| line == "save_replay = replays/match_17/%MAP%_$H$M$S.rep"));
+
+        let ini = BwapiIni {
+            replay_template: Some(DEFAULT_REPLAY_TEMPLATE.to_string()),
+            replay_root: Some(PathBuf::from("D:/scratch/NitekatT")),
+            ..Default::default()
+        };
+        let expected = format!(
+            "save_replay = {}",
+            Path::new("D:/scratch/NitekatT")
+                .join(DEFAULT_REPLAY_TEMPLATE)
+                .to_string_lossy()
+        );
+        assert!(ini_string(&ini).lines().any(|line| line == expected));
+
+        let ini = BwapiIni {
+            replay_template: None,
+            ..Default::default()
+        };
+        assert!(!ini_string(&ini).contains("save_replay"));
+    }
+
+    #[test]
+    fn test_character_name() {
+        assert_eq!(bot_setup("NitekatT").character_name(), "NitekatT");
+        assert_eq!(bot_setup("").character_name(), "ExampleAIModule");
+
+        let join = |name: String| BwapiIni {
+            auto_menu: AutoMenu::Lan {
+                name,
+                race: Some(Race::Terran),
+                game_name: "shotgun".to_string(),
+                connect_mode: BwapiConnectMode::Join,
+                lan_mode: BwapiLanMode::LocalPC,
+                auto_restart: false,
+            },
+            ..Default::default()
+        };
+        assert!(join("".to_string()).write(&mut vec![]).is_err());
+        assert!(ini_string(&join(bot_setup("").character_name()))
+            .lines()
+            .any(|line| line == "character_name=ExampleAIModule"));
+    }
+
+    #[test]
+    fn test_relative_to_bot_dir() {
+        let ini = BwapiIni::from(&bot_setup("NitekatT"));
+        let bot_dir = Path::new("bots/NitekatT");
+        assert_eq!(
+            Path::new(&ini.ai_module),
+            bot_dir.join("bwapi-data/AI/ExampleAIModule.dll")
+        );
+        assert_eq!(ini.tm_module, Some(bot_dir.join("TM_440.dll")));
+        #[cfg(windows)]
+        {
+            let mut on_drive = bot_setup("NitekatT");
+            on_drive.bot_base_path = PathBuf::from(r"C:\bots\NitekatT");
+            assert_eq!(
+                BwapiIni::from(&on_drive).tm_module,
+                Some(PathBuf::from(r"\bots\NitekatT\TM_440.dll"))
+            );
+        }
+
+        let mut rooted = bot_setup("NitekatT");
+        rooted.bot_binary = Binary::Dll(PathBuf::from("/bots/NitekatT/ExampleAIModule.dll"));
+        assert_eq!(
+            BwapiIni::from(&rooted).ai_module,
+            "/bots/NitekatT/ExampleAIModule.dll"
+        );
+    }
+
+    #[test]
+    fn test_ai_module_debug() {
+        let mut out = vec![];
+        BwapiIni {
+            ai_module: "bot.dll".to_string(),
+            ai_module_debug: Some("bot_d.dll".to_string()),
+            line_ending: LineEnding::Lf,
+            ..Default::default()
+        }
+        .write(&mut out)
+        .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("[ai]\nai = bot.dll\nai_dbg = bot_d.dll\n[auto_menu]\n"));
+
+        let mut bot_setup = bot_setup("NitekatT");
+        bot_setup.debug_binary = Some(PathBuf::from("bot_d.dll"));
+        assert_eq!(
+            BwapiIni::from(&bot_setup)
+                .ai_module_debug
+                .map(PathBuf::from),
+            Some(Path::new("bots/NitekatT").join("bot_d.dll"))
+        );
+    }
+
+    #[test]
+    fn test_merge_into() {
+        let ini = BwapiIni {
+            ai_module: "bot.dll".to_string(),
+            game_speed: 0,
+            line_ending: LineEnding::Lf,
+            ..Default::default()
+        };
+        let existing = "; The bot's own settings\n\
+            [ai]\n\
+            ai = old.dll\n\
+            [auto_menu]\n\
+            auto_menu = SINGLE_PLAYER\n\
+            [config]\n\
+            holiday = OFF\n\
+            [starcraft]\n\
+            Sound = ON\n\
+            screenshots = gif\n";
+        assert_eq!(
+            ini.merge_into(existing).unwrap(),
+            "; The bot's own settings\n\
+            [ai]\n\
+            ai = bot.dll\n\
+            [auto_menu]\n\
+            [config]\n\
+            holiday = OFF\n\
+            [starcraft]\n\
+            Sound = ON\n\
+            screenshots = gif\n\
+            speed_override = 0\n"
+        );
+        // Nothing to merge into
+        assert_eq!(ini.merge_into("").unwrap(), ini_string(&ini));
+    }
+
+    #[test]
+    fn test_crlf() {
+        let ini = |line_ending| {
+            let mut out = vec![];
+            BwapiIni {
+                line_ending,
+                ..Default::default()
+            }
+            .write(&mut out)
+            .unwrap();
+            out
+        };
+        let crlf = ini(LineEnding::CrLf);
+        assert!(crlf.starts_with(b"[ai]\r\nai = \r\n"));
+        assert!(crlf.ends_with(b"sound = OFF\r\n"));
+        assert_eq!(
+            crlf.iter().filter(|&&b| b == b'\n').count(),
+            crlf.windows(2).filter(|w| w == b"\r\n").count()
+        );
+        let lf = ini(LineEnding::Lf);
+        assert!(!lf.contains(&b'\r'));
+        assert!(lf.starts_with(b"[ai]\nai = \n"));
+    }
+
+    #[test]
+    fn test_lan_mode_toml() {
+        let game_config = |lan_mode: &str| {
+            toml::from_str::<GameConfig>(&format!(
+                "game_type = {{ Melee = [{{ name = 'A', race = 't' }}] }}\nlan_mode = '{}'",
+                lan_mode
+            ))
+            .unwrap()
+            .lan_mode
+        };
+        assert_eq!(game_config("u"), Some(BwapiLanMode::LocalAreaNetworkUDP));
+        assert_eq!(
+            game_config("LocalAreaNetworkUDP"),
+            Some(BwapiLanMode::LocalAreaNetworkUDP)
+        );
+        assert_eq!(game_config("p"), Some(BwapiLanMode::LocalPC));
+        assert_eq!(game_config("localpc"), Some(BwapiLanMode::LocalPC));
+    }
+
+    #[test]
+    fn test_host_requires_map() {
+        let host = |map: Option<&str>| BwapiIni {
+            auto_menu: AutoMenu::Lan {
+                name: "Bot".to_string(),
+                race: Some(Race::Zerg),
+                game_name: "shotgun".to_string(),
+                connect_mode: BwapiConnectMode::Host {
+                    map: map.map(|map| map.to_string()),
+                    min_players: 2,
+                    max_players: 2,
+                },
+                lan_mode: BwapiLanMode::LocalPC,
+                auto_restart: false,
+            },
+            ..Default::default()
+        };
+        assert!(host(None).write(&mut vec![]).is_err());
+        assert!(host(Some("maps/readme.txt")).write(&mut vec![]).is_err());
+        assert!(ini_string(&host(Some("maps/(2)Destination.scx")))
+            .lines()
+            .any(|line| line == "map=maps/(2)Destination.scx"));
+    }
+
+    #[test]
+    fn test_no_game_table() {
+        let mut game_table_access = GameTableAccess::with_os_id("bwaishotgun_test_no_table");
+        assert_eq!(game_table_access.free_slot_count().unwrap(), GAME_INSTANCES);
+        assert!(game_table_access.occupied_pids().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_open_delays() {
+        let wait = WaitConfig {
+            open_backoff_base: Duration::from_millis(50),
+            open_backoff_max: Duration::from_millis(200),
+            ..Default::default()
+        };
+        assert_eq!(
+            wait.open_delays().take(5).collect::<Vec<_>>(),
+            [50, 100, 200, 200, 200].map(Duration::from_millis)
+        );
+        let wait = WaitConfig {
+            open_timeout: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(wait.open_delays().count(), 0);
+        assert_eq!(wait.delays_until(Some(Instant::now())).count(), 0);
+        // Without a deadline (ie. no lobby timeout), waiting never ends
+        assert_eq!(wait.delays_until(None).take(1000).count(), 1000);
+
+        let mut game_table_access = GameTableAccess::with_os_id("bwaishotgun_test_no_table");
+        let err = game_table_access
+            .wait_for_game_table(&wait, || None)
+            .unwrap_err();
+        assert!(err.to_string().contains("never created the game table"));
+        let err = game_table_access
+            .wait_for_game_table(&WaitConfig::default(), || Some("Interrupted"))
+            .unwrap_err();
+        assert!(err.to_string().contains("Interrupted"));
+        let polled = game_table_access
+            .poll(std::iter::empty(), "Not ready", || None, |_| Ok(false))
+            .unwrap();
+        assert_eq!(polled.unwrap_err().to_string(), "Not ready");
+        // Retrying doesn't help if the game table can't be read
+        assert!(game_table_access
+            .poll(
+                std::iter::empty(),
+                "Not ready",
+                || None,
+                |_| { Err(shared_memory::ShmemError::LinkDoesNotExist) }
+            )
+            .is_err());
+        // Nothing was opened, closing does nothing
+        game_table_access.close();
+        assert!(game_table_access.get_game_table().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_map_value() {
+        assert_eq!(
+            map_value(r"C:\Program Files (x86)\StarCraft\maps\(2)Fighting Spirit.scx").unwrap(),
+            r"C:\Program Files (x86)\StarCraft\maps\(2)Fighting Spirit.scx"
+        );
+        // Would be trimmed or lose its quotes otherwise
+        assert_eq!(
+            map_value(" (2)Destination.scx").unwrap(),
+            "\" (2)Destination.scx\""
+        );
+        let err = map_value("maps/(2)Пустыня.scx").unwrap_err();
+        assert!(err.to_string().contains("only use ASCII"));
+        assert!(map_value("maps/(2)Destination.txt").is_err());
+    }
+
+    #[test]
+    fn test_sanitize_character_name() {
+        assert_eq!(sanitize_character_name("NitekatT"), "NitekatT");
+        assert_eq!(sanitize_character_name("Marine Hell 2"), "Marine Hell 2");
+        assert_eq!(
+            sanitize_character_name("ABCDEFGHIJKLMNOPQRSTUVWXYZ1234"),
+            "ABCDEFGHIJKLMNOPQRSTUVWX"
+        );
+        assert_eq!(sanitize_character_name("Bot\tÄ☃"), "Bot");
+    }
+
+    #[test]
+    fn test_stale_slots() {
+        let instance = |server_process_id, last_keep_alive_time| GameInstance {
+            server_process_id,
+            is_connected: true,
+            last_keep_alive_time,
+        };
+        let mut table = GameTable {
+            game_instances: vec![instance(0, 0); 8],
+        };
+        table.game_instances[0] = instance(1, 20_000);
+        // Tick count wrapped around since the last keep alive
+        table.game_instances[1] = instance(2, u32::MAX - 1_000);
+        table.game_instances[2] = instance(3, u32::MAX - 40_000);
+        assert_eq!(table.stale_slots(30_000, 60_000), vec![2]);
+        assert_eq!(table.live_pids(30_000), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_connected_count() {
+        let instance = |server_process_id, is_connected| GameInstance {
+            server_process_id,
+            is_connected,
+            last_keep_alive_time: 0,
+        };
+        let mut table = GameTable {
+            game_instances: vec![instance(0, false); 8],
+        };
+        table.game_instances[0] = instance(1, true);
+        // Waiting for a client (ie. of another game)
+        table.game_instances[1] = instance(2, false);
+        // Left behind by a crashed server
+        table.game_instances[2] = instance(0, true);
+        table.game_instances[3] = instance(3, true);
+        assert_eq!(table.connected_count(&[1, 2, 3]), 2);
+        // Of another game
+        assert_eq!(table.connected_count(&[1, 2]), 1);
+    }
+
+    #[test]
+    fn test_game_table_from_bytes() {
+        assert_eq!(std::mem::size_of::<GameInstance>(), 12);
+        let mut bytes = vec![0u8; 12 * 8];
+        bytes[12..16].copy_from_slice(&42u32.to_le_bytes());
+        bytes[16] = 1;
+        bytes[20..24].copy_from_slice(&1_000u32.to_le_bytes());
+        let table = GameTable::from_bytes(&bytes).unwrap();
+        assert_eq!(table.game_instances.len(), 8);
+        assert_eq!(
+            SlotState::from(&table.game_instances[1]),
+            SlotState::Connected {
+                server_process_id: 42
+            }
+        );
+        assert_eq!(table.game_instances[1].last_keep_alive_time, 1_000);
+        // Windows maps a whole page
+        bytes.resize(4096, 0);
+        assert_eq!(
+            GameTable::from_bytes(&bytes).unwrap().game_instances.len(),
+            8
+        );
+        // Half written by a BWAPI still starting up
+        bytes[28] = 0xCD;
+        assert!(GameTable::from_bytes(&bytes).is_none());
+    }
+
+    #[test]
+    fn test_slot_state() {
+        let instance = |server_process_id, is_connected| GameInstance {
+            server_process_id,
+            is_connected,
+            last_keep_alive_time: 0,
+        };
+        assert_eq!(SlotState::from(&instance(0, false)), SlotState::Empty);
+        assert_eq!(
+            SlotState::from(&instance(42, false)),
+            SlotState::Waiting {
+                server_process_id: 42
+            }
+        );
+        assert_eq!(
+            SlotState::from(&instance(42, true)),
+            SlotState::Connected {
+                server_process_id: 42
+            }
+        );
+    }
+
+    #[test]
+    fn test_file_version() {
+        let mut exe = b"MZ padding".to_vec();
+        for dword in [0xFEEF04BDu32, 0x00010000, 0x00010010, 0x00010001] {
+            exe.extend_from_slice(&dword.to_le_bytes());
+        }
+        assert_eq!(file_version(&exe), Some([1, 16, 1, 1]));
+        assert_eq!(file_version(&exe[..exe.len() - 1]), None);
+        assert_eq!(file_version(b"MZ"), None);
+        assert_eq!(Bwapi440.starcraft_version(), Some([1, 16, 1]));
+        assert_eq!(BwapiVersion::Unknown(0).starcraft_version(), None);
+    }
 
     #[test]
     fn test_crc() {
@@ -261,12 +1741,43 @@ mod test {
                 .as_slice(),
         );
         assert_eq!(BwapiVersion::from_u32(chksum), Some(Bwapi375));
-        // BWAPI 375 is a replacement for 374
         let chksum = crc.checksum(
             std::fs::read("test-resources/BWAPI374.dll")
                 .unwrap()
                 .as_slice(),
         );
-        assert_eq!(BwapiVersion::from_u32(chksum), Some(Bwapi375));
+        assert_eq!(BwapiVersion::from_u32(chksum), Some(Bwapi374));
+    }
+
+    #[test]
+    fn test_from_dll() {
+        let version = |dll: &str| BwapiVersion::from_dll(Path::new(dll)).unwrap();
+        assert_eq!(version("test-resources/BWAPI440.dll"), Some(Bwapi440));
+        assert_eq!(version("test-resources/BWAPI420.dll"), Some(Bwapi420));
+        assert_eq!(version("test-resources/BWAPI412.dll"), Some(Bwapi412));
+        assert_eq!(version("test-resources/BWAPI375.dll"), Some(Bwapi375));
+        assert_eq!(version("test-resources/BWAPI374.dll"), Some(Bwapi374));
+        assert_eq!(Bwapi374.version_short(), "374");
+        assert_eq!("440".parse::<BwapiVersion>().unwrap(), Bwapi440);
+        assert_eq!("3.7.5".parse::<BwapiVersion>().unwrap(), Bwapi375);
+        assert!("450".parse::<BwapiVersion>().is_err());
+        assert_eq!(Bwapi374.tm_version_short(), Some("375"));
+        assert!(BwapiVersion::from_dll(Path::new("test-resources/missing.dll")).is_err());
+        let tm_version =
+            |tm: &str| BwapiVersion::from_tm_crc(BwapiVersion::dll_crc(Path::new(tm)).unwrap());
+        assert_eq!(tm_version("tm/TM_440.dll"), Some(Bwapi440));
+        assert_eq!(tm_version("tm/TM_375.dll"), Some(Bwapi375));
+        assert_eq!(tm_version("test-resources/BWAPI440.dll"), None);
+        assert_eq!(
+            BwapiVersion::from_u32_lenient(0xDEADBEEF),
+            BwapiVersion::Unknown(0xDEADBEEF)
+        );
+
+        let mut known = vec![];
+        BwapiVersion::write_known(&mut known).unwrap();
+        let known = String::from_utf8(known).unwrap();
+        assert_eq!(known.lines().count(), BwapiVersion::KNOWN.len() + 1);
+        assert!(known.contains("440      0x71CB208B   0x8A3F3155   440"));
+        assert!(known.contains("374      0x41128276   -            375"));
     }
 }
@@ -1,9 +1,9 @@
 use crate::botsetup::{BotSetup, LaunchBuilder};
-use crate::{tools_folder, BwapiIni, BwapiLanMode, GameConfig};
+use crate::{tool, BwapiIni, BwapiLanMode, GameConfig, BWHEADLESS_EXE};
 use anyhow::ensure;
-use std::fs::File;
 use std::process::Command;
 
+#[derive(Debug)]
 pub enum BwHeadlessConnectMode {
     Host { map: String, player_count: usize },
     Join,
@@ -17,6 +17,10 @@ pub struct BwHeadless {
 }
 
 impl LaunchBuilder for BwHeadless {
+    fn bwapi_ini(&self) -> BwapiIni {
+        BwapiIni::from(&self.bot_setup)
+    }
+
     fn build_command(&self, game_config: &GameConfig) -> anyhow::Result<Command> {
         ensure!(
             self.bot_setup.starcraft_exe.exists(),
@@ -35,15 +39,13 @@ impl LaunchBuilder for BwHeadless {
             bwapi_dll.to_string_lossy()
         );
 
-        let bwheadless = tools_folder().join("bwheadless.exe");
+        let bwheadless = tool(game_config.bwheadless_path.as_deref(), BWHEADLESS_EXE);
         ensure!(
             bwheadless.exists(),
             r"Could not find '{}'. Please make sure to extract all files, or check your antivirus software.",
-            tools_folder().to_string_lossy()
+            bwheadless.to_string_lossy()
         );
-        let bwapi_ini = bwapi_data.join("bwapi.ini");
-        let mut bwapi_ini_file = File::create(&bwapi_ini)?;
-        BwapiIni::from(&self.bot_setup).write(&mut bwapi_ini_file)?;
+        let bwapi_ini = self.bot_setup.write_bwapi_ini(&self.bwapi_ini())?;
 
         let mut cmd = self.bot_setup.sandbox.wrap_executable(bwheadless);
         cmd.arg("-e").arg(&self.bot_setup.starcraft_exe);
@@ -53,10 +55,12 @@ impl LaunchBuilder for BwHeadless {
         if self.lan_mode == BwapiLanMode::LocalAreaNetworkUDP {
             cmd.arg("--lan");
         }
-        cmd.arg("-r").arg(&self.bot_setup.race.to_string());
+        if let Some(race) = self.bot_setup.lan_race() {
+            cmd.arg("-r").arg(race.to_string());
+        }
         cmd.arg("-l").arg(bwapi_dll);
         cmd.arg("--installpath").arg(&self.bot_setup.bot_base_path);
-        cmd.arg("-n").arg(&self.bot_setup.player_name);
+        cmd.arg("-n").arg(self.bot_setup.character_name());
         cmd.arg("-gs").arg(game_config.latency_frames.to_string());
         // Newer versions of BWAPI no longer use the registry key (aka installpath) - but allow overriding the bwapi_ini location.
         cmd.env("BWAPI_CONFIG_INI", &*bwapi_ini.to_string_lossy());
@@ -0,0 +1,289 @@
+use std::fs::read;
+use std::time::SystemTime;
+
+use anyhow::bail;
+
+use crate::botsetup::Binary;
+use crate::bwapi::{self, BwapiVersion};
+use crate::sandbox::SandboxMode;
+use crate::{
+    base_folder, format_version, BotDefinition, GameArtifacts, GameConfig, GameType, Shotgun,
+    StarcraftFlavor,
+};
+
+/// Results of `bwaishotgun check`, printed as they come in
+#[derive(Default)]
+struct Checklist {
+    failed: usize,
+}
+
+impl Checklist {
+    fn pass(&self, what: impl AsRef<str>) {
+        println!("[ OK ] {}", what.as_ref());
+    }
+
+    /// Might work, but likely causes trouble
+    fn warn(&self, what: impl AsRef<str>, hint: impl AsRef<str>) {
+        println!("[WARN] {}\n       {}", what.as_ref(), hint.as_ref());
+    }
+
+    /// Games won't start like this
+    fn fail(&mut self, what: impl AsRef<str>, hint: impl AsRef<str>) {
+        self.failed += 1;
+        println!("[FAIL] {}\n       {}", what.as_ref(), hint.as_ref());
+    }
+}
+
+/// Checks everything needed to play the game of `game_config`, fails if a game wouldn't start
+pub fn run(shotgun: &mut Shotgun, game_config: &GameConfig) -> anyhow::Result<()> {
+    let mut checklist = Checklist::default();
+    let flavor = game_config.starcraft_flavor;
+    let starcraft_exe = flavor.executable(&shotgun.starcraft_path);
+    let starcraft_version = if starcraft_exe.is_file() {
+        checklist.pass(format!("Found '{}'", starcraft_exe.to_string_lossy()));
+        read(&starcraft_exe)
+            .ok()
+            .and_then(|exe| bwapi::file_version(&exe))
+    } else {
+        checklist.fail(
+            format!("Could not find '{}'", starcraft_exe.to_string_lossy()),
+            "Set 'starcraft_path' in 'shotgun.toml' to the folder of your StarCraft installation",
+        );
+        None
+    };
+
+    if let Some(map) = game_config.map.as_deref().filter(|map| !map.is_empty()) {
+        match game_config.clone().resolve_map(&shotgun.starcraft_path) {
+            Ok(()) => checklist.pass(format!("Found map '{}'", map)),
+            Err(e) => checklist.fail(
+                format!("{:#}", e),
+                "Maps are looked for in the 'maps' folder of StarCraft",
+            ),
+        }
+    }
+
+    if flavor == StarcraftFlavor::Retail {
+        if shotgun.starcraft_path.join("SNP_DirectIP.snp").is_file() {
+            checklist.pass("Found 'SNP_DirectIP.snp'");
+        } else {
+            checklist.warn(
+                "Could not find 'SNP_DirectIP.snp' in your StarCraft installation",
+                "Copy the provided 'SNP_DirectIP.snp' there, bots can't play each other without it",
+            );
+        }
+        check_game_table(shotgun, &mut checklist);
+    }
+
+    if let SandboxMode::Unconfigured = shotgun.sandbox {
+        checklist.warn(
+            "No sandbox configured, bots run without any isolation",
+            "Set the 'sandbox' in 'shotgun.toml' (or 'NoSandbox' if you're sure)",
+        );
+    }
+
+    let GameType::Melee(bots) = &game_config.game_type;
+    if bots.is_empty() {
+        checklist.warn(
+            "No bots to check",
+            "Add bots to 'game.toml', or select another config with '--config'",
+        );
+    }
+    for bot in bots {
+        check_bot(
+            &bot.name,
+            game_config,
+            flavor,
+            starcraft_version,
+            &mut checklist,
+        );
+    }
+
+    if checklist.failed > 0 {
+        bail!(
+            "{} check(s) failed, games won't start like this",
+            checklist.failed
+        );
+    }
+    println!("All good, ready to play!");
+    Ok(())
+}
+
+fn check_game_table(shotgun: &mut Shotgun, checklist: &mut Checklist) {
+    let os_id = shotgun.game_table_access.os_id().to_string();
+    match shotgun.game_table_access.get_game_table() {
+        Ok(None) => checklist.pass(format!(
+            "BWAPI isn't running ('{}' doesn't exist yet)",
+            os_id
+        )),
+        Ok(Some(game_table)) => {
+            let processes: Vec<_> = game_table
+                .game_instances
+                .iter()
+                .filter(|it| it.is_connected && it.server_process_id != 0)
+                .map(|it| it.server_process_id.to_string())
+                .collect();
+            if processes.is_empty() {
+                checklist.pass(format!("Opened '{}'", os_id));
+            } else {
+                checklist.warn(
+                    format!(
+                        "BWAPI is running already, the processes {} are in the game table",
+                        processes.join(", ")
+                    ),
+                    "Kill all running instances of StarCraft and lingering bots, unless you started them",
+                );
+            }
+        }
+        Err(e) => checklist.fail(
+            format!("Could not open '{}': {}", os_id, e),
+            "Another program might block BWAPI's shared memory, try again after a reboot",
+        ),
+    }
+}
+
+fn check_bot(
+    name: &str,
+    game_config: &GameConfig,
+    flavor: StarcraftFlavor,
+    starcraft_version: Option<[u16; 4]>,
+    checklist: &mut Checklist,
+) {
+    let path = base_folder().join("bots").join(name);
+    let bot_toml = path.join("bot.toml");
+    let definition = match read(&bot_toml)
+        .map_err(anyhow::Error::from)
+        .and_then(|toml| Ok(toml::from_slice::<BotDefinition>(&toml)?))
+    {
+        Ok(definition) => definition,
+        Err(e) => {
+            checklist.fail(
+                format!("'{}': Could not read '{}': {}", name, bot_toml.to_string_lossy(), e),
+                "Copy the 'template' folder in 'bots', rename it to the bot and edit its 'bot.toml'",
+            );
+            return;
+        }
+    };
+
+    // Zipped bots are unzipped to a temp folder, just like for a game
+    let artifacts = GameArtifacts::new(SystemTime::now(), false);
+    let binary = match definition.zip(&path) {
+        Ok(Some(zip)) => Binary::unzip(&zip, &artifacts.unzip_dir(0, name)).map(|(_, it)| it),
+        Ok(None) => definition.binary(&path),
+        Err(e) => Err(e),
+    };
+    let binary = match binary {
+        Ok(binary) => binary,
+        Err(e) => {
+            checklist.fail(
+                format!("'{}': {:#}", name, e),
+                "Place the bot in 'bwapi-data/AI' or set its 'executable' (or zip file) in 'bot.toml'",
+            );
+            return;
+        }
+    };
+    let (binary_path, kind) = match &binary {
+        Binary::Dll(path) => (path, "AI module"),
+        Binary::Jar(path) => (path, "Java client"),
+        Binary::Exe(path) => (path, "client"),
+    };
+    if binary_path.is_file() {
+        checklist.pass(format!(
+            "'{}' is a {}: '{}'",
+            name,
+            kind,
+            binary_path.to_string_lossy()
+        ));
+    } else {
+        checklist.fail(
+            format!(
+                "'{}': Could not find '{}'",
+                name,
+                binary_path.to_string_lossy()
+            ),
+            "Check the 'executable' in 'bot.toml', it's relative to the bot's folder",
+        );
+    }
+
+    if flavor != StarcraftFlavor::Retail {
+        // OpenBW doesn't inject BWAPI.dll
+        return;
+    }
+    let bwapi_dll = path.join("bwapi-data").join("BWAPI.dll");
+    let bwapi_version = match BwapiVersion::from_dll(&bwapi_dll) {
+        Ok(Some(bwapi_version)) => {
+            checklist.pass(format!(
+                "'{}' uses BWAPI {}",
+                name,
+                bwapi_version.version_short()
+            ));
+            bwapi_version
+        }
+        Ok(None) => {
+            let what = format!(
+                "'{}': Unknown BWAPI version of '{}', CRC=0x{:08X}",
+                name,
+                bwapi_dll.to_string_lossy(),
+                BwapiVersion::dll_crc(&bwapi_dll).unwrap_or_default()
+            );
+            let hint = "Use the BWAPI.dll of a BWAPI release, or select its version with '--bwapi-version'";
+            match binary {
+                // Clients bring their own BWAPI, a custom server might work
+                Binary::Dll(_) if game_config.bwapi_version.is_none() => checklist.fail(what, hint),
+                _ if game_config.strict && game_config.bwapi_version.is_none() => {
+                    checklist.fail(what, hint)
+                }
+                _ => checklist.warn(what, hint),
+            }
+            return;
+        }
+        Err(e) => {
+            checklist.fail(
+                format!(
+                    "'{}': Could not read '{}': {}",
+                    name,
+                    bwapi_dll.to_string_lossy(),
+                    e
+                ),
+                "Place the BWAPI.dll the bot was built with in its 'bwapi-data' folder",
+            );
+            return;
+        }
+    };
+    check_starcraft_version(
+        name,
+        bwapi_version,
+        starcraft_version,
+        game_config.strict,
+        checklist,
+    );
+}
+
+fn check_starcraft_version(
+    name: &str,
+    bwapi_version: BwapiVersion,
+    starcraft_version: Option<[u16; 4]>,
+    strict: bool,
+    checklist: &mut Checklist,
+) {
+    let (expected, version) = match (bwapi_version.starcraft_version(), starcraft_version) {
+        (Some(expected), Some(version)) => (expected, version),
+        // Unknown BWAPI or StarCraft is missing, reported already
+        _ => return,
+    };
+    if version[..3] == expected {
+        return;
+    }
+    let what = format!(
+        "'{}': BWAPI {} needs StarCraft {}, but it's {}",
+        name,
+        bwapi_version.version_short(),
+        format_version(&expected),
+        format_version(&version)
+    );
+    let hint = "Install StarCraft 1.16.1 (see the README)";
+    if strict {
+        checklist.fail(what, hint);
+    } else {
+        checklist.warn(what, hint);
+    }
+}
@@ -1,72 +1,931 @@
-use crate::{BotLaunchConfig, BwapiLanMode, GameConfig, HeadfulMode};
+use crate::bench::Bench;
+use crate::tournament::{Series, Tournament};
+use crate::{
+    BotLaunchConfig, BwapiLanMode, BwapiVersion, GameConfig, GameSpeed, HeadfulMode, Race,
+    StarcraftFlavor, WaitConfig, DEFAULT_LATENCY_FRAMES,
+};
 use clap::{ErrorKind, Parser, Subcommand};
+use log::LevelFilter;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Subcommand, Debug)]
 enum GameType {
     /// Host a melee game
     Melee {
-        /// Names of bots to play
+        /// Names of bots to play, optionally with a race (ie. 'NitekatT:p')
         bots: Vec<String>,
     },
     /// You will host a game the bots can join (make sure to select Local PC network)
     Human {
-        /// Names of bots to play
+        /// Names of bots to play, optionally with a race (ie. 'NitekatT:p')
         bots: Vec<String>,
     },
+    /// Join a game hosted by someone else (ie. another tool or a human)
+    Join {
+        /// Name of the game to join
+        game_name: String,
+        /// Names of bots to play, optionally with a race (ie. 'NitekatT:p')
+        bots: Vec<String>,
+    },
+    /// Play a round robin tournament, each bot plays every other bot once per round
+    Tournament {
+        /// Names of bots to play, optionally with a race (ie. 'NitekatT:p,ZergHell')
+        #[clap(long, required = true, use_value_delimiter = true)]
+        bots: Vec<String>,
+        #[clap(long, default_value_t = 1)]
+        rounds: u32,
+        /// Also write the standings to this CSV file
+        #[clap(long)]
+        csv: Option<PathBuf>,
+        /// Games to play at the same time (limited by the slots of BWAPI's game table)
+        #[clap(long, default_value_t = 1)]
+        max_parallel: usize,
+        /// Seconds to wait after a game, for BWAPI to release its slots before the next game
+        #[clap(long, default_value_t = 2)]
+        inter_game_delay: u64,
+    },
+    /// Play a series between two bots, until one of them won the majority of games
+    Series {
+        /// Names of the two bots, optionally with a race (ie. 'NitekatT:p ZergHell')
+        #[clap(
+            long,
+            required = true,
+            multiple_values = true,
+            use_value_delimiter = true
+        )]
+        bots: Vec<String>,
+        #[clap(long, default_value_t = 3)]
+        best_of: u32,
+        /// Seconds to wait after a game, for BWAPI to release its slots before the next game
+        #[clap(long, default_value_t = 2)]
+        inter_game_delay: u64,
+    },
+    /// Play the same game over and over and show how long launching, starting and stopping it
+    /// took (without bots, the ones of the game config play)
+    Bench {
+        /// Names of bots to play, optionally with a race (ie. 'NitekatT:p')
+        bots: Vec<String>,
+        #[clap(long, default_value_t = 10)]
+        games: u32,
+        /// Seconds to wait after a game, for BWAPI to release its slots before the next game
+        #[clap(long, default_value_t = 2)]
+        inter_game_delay: u64,
+    },
+    /// Check StarCraft, BWAPI and the bots of the game config, without playing
+    Check,
+    /// Print the game table as a JSON line every '--interval' ms until stopped, without
+    /// launching anything (ie. to watch games started by another shotgun)
+    Watch {
+        #[clap(long, default_value_t = 1000)]
+        interval: u64,
+    },
+    /// Only print the BWAPI.ini the bot would host a game with (the same input gives the same output)
+    EmitIni {
+        /// Name of the bot, optionally with a race (ie. 'NitekatT:p')
+        #[clap(long)]
+        bot: String,
+    },
+}
+
+impl GameType {
+    /// Benchmarks without bots play the game of the game config
+    fn uses_config_bots(&self) -> bool {
+        matches!(self, GameType::Bench { bots, .. } if bots.is_empty())
+    }
 }
 
 #[derive(Parser, Debug)]
 pub struct Cli {
-    /// Absolute path of map to host
+    /// Game configuration to use instead of 'game.toml', other arguments override its values
     #[clap(short, long)]
+    pub config: Option<PathBuf>,
+    /// StarCraft folder to use, instead of the one of 'shotgun.toml', STARCRAFT_DIR or the registry
+    #[clap(long)]
+    pub starcraft_dir: Option<PathBuf>,
+    /// Map to host, relative to StarCraft's 'maps' folder (or StarCraft itself, or absolute)
+    #[clap(short, long, global = true)]
     map: Option<String>,
+    /// Name of the game in the lobby, to host or join (default: a unique one, or the first game
+    /// found when joining a human)
+    #[clap(long, global = true)]
+    game_name: Option<String>,
+    /// Folder of maps to pick a random map from (instead of '--map')
+    #[clap(long, conflicts_with = "map", global = true)]
+    map_dir: Option<PathBuf>,
     #[clap(subcommand)]
     game_type: Option<GameType>,
+    /// Same as '--speed human'
     #[clap(short, long)]
     human_speed: bool,
+    /// fastest (42), fast (56), normal (67), slow (83), human (-1, no override) or milliseconds per
+    /// frame (BWAPI's 'speed_override'), 0 for full throttle
+    #[clap(
+        long,
+        alias = "game-speed",
+        conflicts_with = "human-speed",
+        allow_hyphen_values = true
+    )]
+    speed: Option<GameSpeed>,
+    /// Turn on sound, also for bots with a StarCraft window that would play without
+    #[clap(long, overrides_with = "no-sound")]
+    sound: bool,
+    /// Turn off sound, also for bots with a StarCraft window (the last of '--sound' and
+    /// '--no-sound' wins)
+    #[clap(long, overrides_with = "sound")]
+    no_sound: bool,
+    /// Let the only bot play against the computer (needs '--headful')
+    #[clap(long)]
+    vs_computer: bool,
+    /// Race of a computer opponent, implies '--vs-computer' (can be repeated for more computers)
+    #[clap(long)]
+    computer: Vec<Race>,
+    /// Frames of latency, raise this for bots needing more time per frame (default: 3, max: 12)
+    #[clap(long)]
+    latency_frames: Option<u32>,
+    /// Latency and speed of a ladder: 'sscait' (3 frames, fastest), 'aiide' (3 frames, as fast
+    /// as possible) or 'low' (1 frame). '--latency-frames' and '--speed' still override it
+    #[clap(long)]
+    latency_preset: Option<LatencyPreset>,
+    /// Milliseconds between checks for BWAPI servers and clients being ready (default: 100)
+    #[clap(long)]
+    poll_interval: Option<u64>,
+    /// Milliseconds to wait for BWAPI servers and clients to be ready (default: 10000)
+    #[clap(long)]
+    max_wait: Option<u64>,
     #[clap(arg_enum)]
     lan_mode: Option<BwapiLanMode>,
+    /// Race of all bots without an explicit race (z/p/t/r or the full name)
+    #[clap(short, long)]
+    race: Option<Race>,
+    /// Seed for random picks (map, race and game name), to reproduce a game
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Pick a concrete race for bots playing Random, instead of letting BWAPI pick one
+    #[clap(long)]
+    roll_random_race: bool,
+    /// Launch the bots in a random order, so they don't always get the same slot (see '--seed').
+    /// Series alternate the host instead and refuse this
+    #[clap(long)]
+    shuffle_slots: bool,
+    /// Only write the BWAPI.ini of each bot and show what would be started
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Don't start games with a StarCraft the bots' BWAPI wasn't built for, or with an unknown
+    /// BWAPI.dll (also for client bots)
+    #[clap(long)]
+    strict: bool,
+    /// Treat the BWAPI.dll of all bots as this version (374, 375, 412, 420 or 440), ie. for
+    /// builds that aren't known yet
+    #[clap(long)]
+    bwapi_version: Option<BwapiVersion>,
+    /// Use this injectory, ie. a patched build, instead of the bundled one
+    #[clap(long)]
+    injectory: Option<PathBuf>,
+    /// Use this bwheadless, ie. a patched build, instead of the bundled one
+    #[clap(long)]
+    bwheadless: Option<PathBuf>,
+    /// Keep the files written for each game (ie. the BWAPI.ini of each bot) in the temp folder,
+    /// and show where the BWAPI.ini, logs and replays of each bot are
+    #[clap(long)]
+    keep_artifacts: bool,
+    /// Don't stop StarCraft windows at the end of the game (also not at timeouts), wait until
+    /// they are closed instead. Needs a bot with '--headful'
+    #[clap(long)]
+    keep_open: bool,
+    /// Zip the replays after the game, the '.rep' files are deleted
+    #[clap(long)]
+    compress_replays: bool,
+    /// Count frames a bot takes longer than this many ms for as slow frames (default: 55)
+    #[clap(long)]
+    slow_frame_ms: Option<u32>,
+    /// Run this bot with a StarCraft window, as 'bot' or 'bot:minimized' (can be repeated).
+    /// Minimized StarCraft still renders the game, it's not the same as headless
+    #[clap(long)]
+    headful: Vec<String>,
+    /// Run all bots with a StarCraft window
+    #[clap(long)]
+    headful_all: bool,
+    /// Run the 'debug_executable' of this bot (can be repeated)
+    #[clap(long)]
+    debug_bot: Vec<String>,
+    /// In-game name of a bot, as 'bot=name' (can be repeated)
+    #[clap(long)]
+    player_name: Vec<String>,
+    /// Cores StarCraft and a bot may run on, as 'bot=0,1' or 'bot=0-3' (can be repeated)
+    #[clap(long)]
+    affinity: Vec<String>,
+    /// Show the supported BWAPI versions and their CRCs
+    #[clap(long)]
+    pub list_versions: bool,
+    /// Show more output (repeat for even more)
+    #[clap(short, long, parse(from_occurrences), conflicts_with = "quiet")]
+    verbose: u8,
+    /// Show only warnings (repeat for errors only)
+    #[clap(short, long, parse(from_occurrences))]
+    quiet: u8,
+}
+
+const HEADFUL_ON: HeadfulMode = HeadfulMode::On {
+    no_wmode: false,
+    no_sound: false,
+    resolution: None,
+};
+
+/// Parses 'bot' or 'bot:minimized' of `--headful`
+fn parse_headful(headful: &str) -> (&str, HeadfulMode) {
+    match headful.strip_suffix(":minimized") {
+        Some(name) => (name, HeadfulMode::Minimized { no_sound: false }),
+        None => (headful, HEADFUL_ON),
+    }
 }
 
+/// Why the command line can't be used, each with its own exit code for scripts
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    NoArguments,
     ClapError(clap::Error),
+    /// A bot given as 'name:race' with a race that isn't one
+    InvalidRaceToken {
+        bot: String,
+        reason: String,
+    },
+    /// An option like '--headful' names a bot that doesn't play
+    UnknownBot {
+        option: &'static str,
+        value: String,
+    },
+    /// '--map' or '--map-dir' are not in StarCraft (or don't exist at all)
+    MapNotFound(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            // As `clap::Error::exit`: Help and version are no errors
+            Error::ClapError(e) if !e.use_stderr() => 0,
+            Error::ClapError(_) => 2,
+            Error::InvalidRaceToken { .. } => 3,
+            Error::UnknownBot { .. } => 4,
+            Error::MapNotFound(_) => 5,
+        }
+    }
+
+    /// Prints the error and exits with its `exit_code`
+    pub fn exit(self) -> ! {
+        match self {
+            Error::ClapError(e) => e.exit(),
+            e => {
+                eprintln!("error: {}", e);
+                std::process::exit(e.exit_code())
+            }
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ClapError(e) => write!(f, "{}", e),
+            Error::InvalidRaceToken { bot, reason } => {
+                write!(f, "Could not parse bot '{}': {}", bot, reason)
+            }
+            Error::UnknownBot { option, value } => {
+                write!(f, "'{} {}' is not one of the bots to play", option, value)
+            }
+            Error::MapNotFound(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<clap::Error> for Error {
+    fn from(e: clap::Error) -> Self {
+        Error::ClapError(e)
+    }
+}
+
+impl Cli {
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.verbose, self.quiet) {
+            (0, 0) => LevelFilter::Info,
+            (1, _) => LevelFilter::Debug,
+            (_, 0) => LevelFilter::Trace,
+            (_, 1) => LevelFilter::Warn,
+            _ => LevelFilter::Error,
+        }
+    }
+
+    /// The games to play, if a tournament was selected
+    pub fn tournament(&self) -> Option<Tournament> {
+        match &self.game_type {
+            Some(GameType::Tournament {
+                rounds,
+                csv,
+                max_parallel,
+                inter_game_delay,
+                ..
+            }) => Some(Tournament {
+                rounds: *rounds,
+                csv: csv.clone(),
+                max_parallel: *max_parallel,
+                inter_game_delay: Duration::from_secs(*inter_game_delay),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The games to play, if a series was selected
+    pub fn series(&self) -> Option<Series> {
+        match &self.game_type {
+            Some(GameType::Series {
+                best_of,
+                inter_game_delay,
+                ..
+            }) => Some(Series {
+                best_of: *best_of,
+                inter_game_delay: Duration::from_secs(*inter_game_delay),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The games to benchmark, if a bench was selected
+    pub fn bench(&self) -> Option<Bench> {
+        match &self.game_type {
+            Some(GameType::Bench {
+                games,
+                inter_game_delay,
+                ..
+            }) => Some(Bench {
+                games: *games,
+                inter_game_delay: Duration::from_secs(*inter_game_delay),
+            }),
+            _ => None,
+        }
+    }
+
+    /// A game was given on the command line, instead of in a config file
+    pub fn has_game(&self) -> bool {
+        self.map.is_some()
+            || self.map_dir.is_some()
+            || matches!(&self.game_type, Some(game_type) if !matches!(game_type, GameType::Check | GameType::Watch { .. }) && !game_type.uses_config_bots())
+    }
+
+    /// Only check the setup, see `check::run`
+    pub fn check(&self) -> bool {
+        matches!(self.game_type, Some(GameType::Check))
+    }
+
+    /// Poll interval of `bwaishotgun watch`, nothing else may go to stdout
+    pub fn watch(&self) -> Option<Duration> {
+        match self.game_type {
+            Some(GameType::Watch { interval }) => Some(Duration::from_millis(interval)),
+            _ => None,
+        }
+    }
+
+    /// Only print the BWAPI.ini of a bot, nothing else may go to stdout
+    pub fn emit_ini(&self) -> bool {
+        matches!(self.game_type, Some(GameType::EmitIni { .. }))
+    }
+
+    /// The maps of '--map' and '--map-dir' exist, as far as that can be told before preparing
+    /// the game config
+    pub fn check_maps(&self, starcraft_path: &Path) -> Result<(), Error> {
+        if let Some(map) = &self.map {
+            crate::envvars::expand(map)
+                .and_then(|map| crate::map::resolve_map(starcraft_path, &map))
+                .map_err(|e| Error::MapNotFound(format!("{:#}", e)))?;
+        }
+        if let Some(map_dir) = &self.map_dir {
+            if !starcraft_path.join(map_dir).exists() {
+                return Err(Error::MapNotFound(format!(
+                    "Could not find the maps of '--map-dir {}' in '{}'",
+                    map_dir.to_string_lossy(),
+                    starcraft_path.to_string_lossy()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Overrides the values of `config` with the ones given on the command line
+    pub fn apply(self, config: &mut GameConfig) -> Result<(), Error> {
+        if let Some(map) = self.map {
+            config.map = Some(map);
+            config.map_pool.clear();
+        }
+        if let Some(map_dir) = self.map_dir {
+            config.map = None;
+            config.map_pool = vec![map_dir];
+        }
+        if let Some(GameType::EmitIni { bot }) = &self.game_type {
+            config.game_type = crate::GameType::Melee(vec![parse_bot(bot)?]);
+            config.human_host = false;
+        }
+        if let Some(game_type) = self.game_type.filter(|game_type| {
+            !matches!(
+                game_type,
+                GameType::Check | GameType::Watch { .. } | GameType::EmitIni { .. }
+            ) && !game_type.uses_config_bots()
+        }) {
+            let bots = match &game_type {
+                GameType::Melee { bots }
+                | GameType::Human { bots }
+                | GameType::Join { bots, .. }
+                | GameType::Tournament { bots, .. }
+                | GameType::Series { bots, .. }
+                | GameType::Bench { bots, .. } => bots,
+                GameType::Check | GameType::Watch { .. } | GameType::EmitIni { .. } => {
+                    unreachable!("Only games are set up here")
+                }
+            };
+            if matches!(game_type, GameType::Tournament { .. }) && bots.len() < 2 {
+                return Err(Error::ClapError(clap::Error::raw(
+                    ErrorKind::TooFewValues,
+                    "A tournament needs at least 2 bots\n",
+                )));
+            }
+            if matches!(
+                game_type,
+                GameType::Tournament {
+                    max_parallel: 0,
+                    ..
+                }
+            ) {
+                return Err(Error::ClapError(clap::Error::raw(
+                    ErrorKind::InvalidValue,
+                    "'--max-parallel' must be at least 1\n",
+                )));
+            }
+            if let GameType::Series { best_of, .. } = game_type {
+                if bots.len() != 2 {
+                    return Err(Error::ClapError(clap::Error::raw(
+                        ErrorKind::WrongNumberOfValues,
+                        "A series is played by exactly 2 bots\n",
+                    )));
+                }
+                if best_of == 0 {
+                    return Err(Error::ClapError(clap::Error::raw(
+                        ErrorKind::InvalidValue,
+                        "A series needs at least one game\n",
+                    )));
+                }
+            }
+            config.game_type = crate::GameType::Melee(
+                bots.iter()
+                    .map(|bot| parse_bot(bot))
+                    .collect::<Result<_, _>>()?,
+            );
+            config.human_host = matches!(game_type, GameType::Human { .. } | GameType::Join { .. });
+            if let GameType::Join { game_name, .. } = game_type {
+                if game_name.trim().is_empty() {
+                    return Err(Error::ClapError(clap::Error::raw(
+                        ErrorKind::InvalidValue,
+                        "The name of the game to join must not be empty\n",
+                    )));
+                }
+                config.game_name = Some(game_name);
+            }
+        }
+        if let Some(game_name) = self.game_name {
+            config.game_name = Some(game_name);
+        }
+        if let Some(preset) = self.latency_preset {
+            config.latency_frames = preset.latency_frames();
+            if let Some(speed) = preset.game_speed() {
+                config.human_speed = false;
+                config.game_speed = Some(speed);
+            }
+        }
+        if self.human_speed {
+            config.human_speed = false;
+            config.game_speed = Some(GameSpeed::Human);
+        }
+        if let Some(speed) = self.speed {
+            config.human_speed = false;
+            config.game_speed = Some(speed);
+        }
+        if self.sound {
+            config.sound = Some(true);
+        }
+        if self.no_sound {
+            config.sound = Some(false);
+        }
+        config.vs_computer |= self.vs_computer || !self.computer.is_empty();
+        if !self.computer.is_empty() {
+            config.computer_races = self.computer;
+        }
+        if let Some(latency_frames) = self.latency_frames {
+            config.latency_frames = latency_frames;
+        }
+        if let Some(poll_interval) = self.poll_interval {
+            config.wait.poll_interval = Duration::from_millis(poll_interval);
+        }
+        if let Some(max_wait) = self.max_wait {
+            config.wait.max_wait = Duration::from_millis(max_wait);
+        }
+        if let Some(lan_mode) = self.lan_mode {
+            config.lan_mode = Some(lan_mode);
+        }
+        if let Some(seed) = self.seed {
+            config.seed = Some(seed);
+        }
+        config.roll_random_race |= self.roll_random_race;
+        config.shuffle_slots |= self.shuffle_slots;
+        config.dry_run |= self.dry_run;
+        config.keep_artifacts |= self.keep_artifacts;
+        config.keep_open |= self.keep_open;
+        config.compress_replays |= self.compress_replays;
+        if let Some(slow_frame_ms) = self.slow_frame_ms {
+            config.slow_frame_ms = slow_frame_ms;
+        }
+        config.strict |= self.strict;
+        if let Some(bwapi_version) = self.bwapi_version {
+            config.bwapi_version = Some(bwapi_version);
+        }
+        if let Some(injectory) = &self.injectory {
+            config.injectory_path = Some(injectory.clone());
+        }
+        if let Some(bwheadless) = &self.bwheadless {
+            config.bwheadless_path = Some(bwheadless.clone());
+        }
+
+        let crate::GameType::Melee(bots) = &mut config.game_type;
+        if let Some(race) = self.race {
+            for bot in bots.iter_mut().filter(|bot| bot.race.is_none()) {
+                bot.race = Some(race);
+            }
+        }
+        for headful in &self.headful {
+            let (name, mode) = parse_headful(headful);
+            let mut found = false;
+            for bot in bots.iter_mut().filter(|bot| bot.name == name) {
+                bot.headful = mode;
+                found = true;
+            }
+            if !found {
+                return Err(Error::UnknownBot {
+                    option: "--headful",
+                    value: headful.clone(),
+                });
+            }
+        }
+        for debug_bot in &self.debug_bot {
+            let mut found = false;
+            for bot in bots.iter_mut().filter(|bot| &bot.name == debug_bot) {
+                bot.debug = true;
+                found = true;
+            }
+            if !found {
+                return Err(Error::UnknownBot {
+                    option: "--debug-bot",
+                    value: debug_bot.clone(),
+                });
+            }
+        }
+        for player_name in &self.player_name {
+            let (name, display) = player_name
+                .split_once('=')
+                .filter(|(name, display)| !name.is_empty() && !display.is_empty())
+                .ok_or_else(|| {
+                    Error::ClapError(clap::Error::raw(
+                        ErrorKind::InvalidValue,
+                        format!(
+                            "'--player-name {}' must be given as 'bot=name'\n",
+                            player_name
+                        ),
+                    ))
+                })?;
+            let mut found = false;
+            for bot in bots.iter_mut().filter(|bot| bot.name == name) {
+                bot.player_name = Some(display.to_string());
+                found = true;
+            }
+            if !found {
+                return Err(Error::UnknownBot {
+                    option: "--player-name",
+                    value: player_name.clone(),
+                });
+            }
+        }
+        for affinity in &self.affinity {
+            let invalid = |reason: &str| {
+                Error::ClapError(clap::Error::raw(
+                    ErrorKind::InvalidValue,
+                    format!("'--affinity {}' {}\n", affinity, reason),
+                ))
+            };
+            let (name, cores) = affinity
+                .split_once('=')
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| invalid("must be given as 'bot=cores'"))?;
+            let cores = parse_cores(cores).ok_or_else(|| invalid("has invalid cores"))?;
+            let mut found = false;
+            for bot in bots.iter_mut().filter(|bot| bot.name == name) {
+                bot.cpu_affinity = Some(cores.clone());
+                found = true;
+            }
+            if !found {
+                return Err(Error::UnknownBot {
+                    option: "--affinity",
+                    value: affinity.clone(),
+                });
+            }
+        }
+        if self.headful_all {
+            for bot in bots.iter_mut() {
+                bot.headful = HEADFUL_ON;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<Cli> for GameConfig {
     type Error = Error;
 
     fn try_from(cli: Cli) -> Result<Self, Self::Error> {
-        if cli.map.is_none() && cli.game_type.is_none() {
-            Err(Error::NoArguments)
-        } else if cli.map.is_some() != cli.game_type.is_some() {
-            Err(Error::ClapError(clap::Error::raw(
+        let has_map = cli.map.is_some() || cli.map_dir.is_some();
+        // Joined games are set up by the host
+        let needs_map = !matches!(cli.game_type, Some(GameType::Join { .. }));
+        if has_map && cli.game_type.is_none() || needs_map && !has_map {
+            return Err(Error::ClapError(clap::Error::raw(
                 ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand,
                 "Either no or all arguments are required. Use '-h' to get help.\n",
-            )))
-        } else {
-            let game_type = match cli.game_type.as_ref().expect("Game Type not set") {
-                GameType::Melee { bots } | GameType::Human { bots } => crate::GameType::Melee(
-                    bots.iter()
-                        .map(|name| BotLaunchConfig {
-                            name: name.to_string(),
-                            player_name: None,
-                            race: None,
-                            headful: HeadfulMode::Off,
-                        })
-                        .collect(),
-                ),
-            };
-            Ok(GameConfig {
-                map: cli.map,
-                game_name: None,
-                game_type,
-                human_host: matches!(cli.game_type.unwrap(), GameType::Human { .. }),
-                human_speed: cli.human_speed,
-                latency_frames: 3,
-                lan_mode: cli.lan_mode,
-                time_out_at_frame: None,
-            })
+            )));
+        }
+        let mut game_config = GameConfig {
+            map: None,
+            map_pool: vec![],
+            seed: None,
+            game_name: None,
+            game_type: crate::GameType::Melee(vec![]),
+            starcraft_flavor: StarcraftFlavor::Retail,
+            human_host: false,
+            min_players: None,
+            max_players: None,
+            vs_computer: false,
+            computer_races: vec![],
+            human_speed: false,
+            game_speed: None,
+            sound: None,
+            latency_frames: DEFAULT_LATENCY_FRAMES,
+            lan_mode: None,
+            time_out_at_frame: None,
+            time_out_at_seconds: None,
+            replay_template: None,
+            replay_root: None,
+            dry_run: false,
+            keep_artifacts: false,
+            keep_open: false,
+            compress_replays: false,
+            slow_frame_ms: crate::DEFAULT_SLOW_FRAME_MS,
+            strict: false,
+            bwapi_version: None,
+            exact_map_players: false,
+            injectory_path: None,
+            bwheadless_path: None,
+            auto_restart: false,
+            roll_random_race: false,
+            shuffle_slots: false,
+            launch_retries: 0,
+            wait: WaitConfig::default(),
+        };
+        cli.apply(&mut game_config)?;
+        Ok(game_config)
+    }
+}
+
+/// Parses cores given as `0,2` or ranges like `0-3`, `None` if there are none or they're invalid
+fn parse_cores(cores: &str) -> Option<Vec<usize>> {
+    let mut parsed = vec![];
+    for part in cores.split(',').map(str::trim) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last): (usize, usize) =
+                    (first.trim().parse().ok()?, last.trim().parse().ok()?);
+                if first > last {
+                    return None;
+                }
+                parsed.extend(first..=last);
+            }
+            None => parsed.push(part.parse().ok()?),
+        }
+    }
+    Some(parsed)
+}
+
+/// Parses a bot given as `name` or `name:race`
+fn parse_bot(bot: &str) -> Result<BotLaunchConfig, Error> {
+    let (name, race) = match bot.rsplit_once(':') {
+        Some((name, race)) => (
+            name,
+            Some(race.parse().map_err(|e| Error::InvalidRaceToken {
+                bot: bot.to_string(),
+                reason: format!("{}", e),
+            })?),
+        ),
+        None => (bot, None),
+    };
+    if name.trim().is_empty() {
+        return Err(Error::ClapError(clap::Error::raw(
+            ErrorKind::InvalidValue,
+            format!("Could not parse bot '{}': The name is missing\n", bot),
+        )));
+    }
+    Ok(BotLaunchConfig {
+        name: name.to_string(),
+        player_name: None,
+        race,
+        headful: HeadfulMode::Off,
+        debug: false,
+        cpu_affinity: None,
+        priority: None,
+        jvm_args: vec![],
+        observer: false,
+    })
+}
+
+/// Settings of the ladders, so games can be played under the same conditions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LatencyPreset {
+    /// 3 latency frames at "Fastest" (42ms per frame), as SSCAIT streams its games
+    Sscait,
+    /// 3 latency frames as fast as possible, as AIIDE runs its games
+    Aiide,
+    /// 1 latency frame, the game speed is not changed
+    Low,
+}
+
+impl LatencyPreset {
+    pub fn latency_frames(&self) -> u32 {
+        match self {
+            LatencyPreset::Sscait | LatencyPreset::Aiide => 3,
+            LatencyPreset::Low => 1,
         }
     }
+
+    pub fn game_speed(&self) -> Option<GameSpeed> {
+        match self {
+            LatencyPreset::Sscait => Some(GameSpeed::Fastest),
+            LatencyPreset::Aiide => Some(GameSpeed::Millis(0)),
+            LatencyPreset::Low => None,
+        }
+    }
+}
+
+impl FromStr for LatencyPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sscait" => Ok(LatencyPreset::Sscait),
+            "aiide" => Ok(LatencyPreset::Aiide),
+            "low" => Ok(LatencyPreset::Low),
+            _ => anyhow::bail!(
+                "Invalid latency preset '{}', expected one of sscait/aiide/low",
+                s
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::{parse_bot, parse_cores, parse_headful, Cli, Error, LatencyPreset};
+    use crate::{GameConfig, HeadfulMode, Race};
+    use clap::Parser;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_bot() {
+        let bot = parse_bot("BotA:P").ok().unwrap();
+        assert_eq!(bot.name, "BotA");
+        assert_eq!(bot.race, Some(Race::Protoss));
+        assert_eq!(parse_bot("BotB:z").ok().unwrap().race, Some(Race::Zerg));
+        assert_eq!(
+            parse_bot("BotC:random").ok().unwrap().race,
+            Some(Race::Random)
+        );
+        let bot = parse_bot("BotD").ok().unwrap();
+        assert_eq!(bot.name, "BotD");
+        assert_eq!(bot.race, None);
+
+        let err = parse_bot("BotE:X").err().unwrap();
+        assert!(matches!(err, Error::InvalidRaceToken { .. }));
+        assert!(err.to_string().contains("'BotE:X'"));
+        assert_eq!(err.exit_code(), 3);
+        assert!(parse_bot(":t").is_err());
+    }
+
+    #[test]
+    fn test_parse_cores() {
+        assert_eq!(parse_cores("3"), Some(vec![3]));
+        assert_eq!(parse_cores("0, 2"), Some(vec![0, 2]));
+        assert_eq!(parse_cores("0-3,6"), Some(vec![0, 1, 2, 3, 6]));
+        assert_eq!(parse_cores(""), None);
+        assert_eq!(parse_cores("3-1"), None);
+        assert_eq!(parse_cores("a"), None);
+    }
+
+    #[test]
+    fn test_parse_headful() {
+        assert!(matches!(
+            parse_headful("BotA"),
+            ("BotA", HeadfulMode::On { .. })
+        ));
+        assert!(matches!(
+            parse_headful("BotA:minimized"),
+            ("BotA", HeadfulMode::Minimized { no_sound: false })
+        ));
+    }
+
+    #[test]
+    fn test_sound_last_wins() {
+        let sound = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["shotgun"].iter().chain(args)).unwrap();
+            (cli.sound, cli.no_sound)
+        };
+        assert_eq!(sound(&[]), (false, false));
+        assert_eq!(sound(&["--sound", "--no-sound"]), (false, true));
+        assert_eq!(sound(&["--no-sound", "--sound"]), (true, false));
+    }
+
+    #[test]
+    fn test_latency_preset() {
+        assert_eq!(
+            "SSCAIT".parse::<LatencyPreset>().ok(),
+            Some(LatencyPreset::Sscait)
+        );
+        assert_eq!(
+            "low".parse::<LatencyPreset>().ok(),
+            Some(LatencyPreset::Low)
+        );
+        assert!("bwl".parse::<LatencyPreset>().is_err());
+        assert_eq!(
+            LatencyPreset::Aiide.game_speed().map(|it| it.to_override()),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_inter_game_delay() {
+        let cli = Cli::try_parse_from(["shotgun", "series", "--bots", "BotA", "BotB"]).unwrap();
+        assert_eq!(
+            cli.series().unwrap().inter_game_delay,
+            Duration::from_secs(2)
+        );
+        let cli = Cli::try_parse_from([
+            "shotgun",
+            "tournament",
+            "--bots",
+            "BotA,BotB",
+            "--inter-game-delay",
+            "0",
+        ])
+        .unwrap();
+        assert_eq!(cli.tournament().unwrap().inter_game_delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_bench() {
+        let cli = Cli::try_parse_from(["shotgun", "bench", "--games", "20"]).unwrap();
+        assert_eq!(cli.bench().unwrap().games, 20);
+        // The bots of 'game.toml' play
+        assert!(!cli.has_game());
+        let cli = Cli::try_parse_from(["shotgun", "--map", "SomeMap.scm", "bench", "BotA", "BotB"])
+            .unwrap();
+        assert!(cli.has_game());
+        let game_config = GameConfig::try_from(cli).unwrap();
+        let crate::GameType::Melee(bots) = game_config.game_type;
+        assert_eq!(bots.len(), 2);
+    }
+
+    #[test]
+    fn test_unknown_bot() {
+        let cli = Cli::try_parse_from([
+            "shotgun",
+            "--map",
+            "SomeMap.scm",
+            "--debug-bot",
+            "BotB",
+            "melee",
+            "BotA",
+        ])
+        .unwrap();
+        let err = GameConfig::try_from(cli).err().unwrap();
+        assert!(matches!(
+            err,
+            Error::UnknownBot {
+                option: "--debug-bot",
+                ..
+            }
+        ));
+        assert_eq!(err.exit_code(), 4);
+    }
 }
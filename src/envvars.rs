@@ -0,0 +1,75 @@
+use anyhow::bail;
+
+/// Replaces `%VAR%`, `$VAR` and `${VAR}` with the environment variable, so configs work on
+/// other machines as well. Fails if a variable is not set.
+pub fn expand(s: &str) -> anyhow::Result<String> {
+    expand_with(s, |name| std::env::var(name).ok())
+}
+
+fn expand_with(s: &str, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find(['%', '$']) {
+        expanded.push_str(&rest[..start]);
+        let token = &rest[start..];
+        let (name, len) = if let Some(windows) = token.strip_prefix('%') {
+            match windows.find('%') {
+                Some(end) => (&windows[..end], end + 2),
+                None => ("", 1),
+            }
+        } else if let Some(braced) = token.strip_prefix("${") {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 3),
+                None => ("", 1),
+            }
+        } else {
+            let name_len = token[1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(token.len() - 1);
+            (&token[1..1 + name_len], name_len + 1)
+        };
+        if name.is_empty() {
+            // Not a variable, ie. "%%" or a lone "$"
+            expanded.push_str(&token[..len]);
+        } else {
+            match var(name) {
+                Some(value) => expanded.push_str(&value),
+                None => bail!(
+                    "Environment variable '{}' (used in '{}') is not set",
+                    name,
+                    s
+                ),
+            }
+        }
+        rest = &token[len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::envvars::expand_with;
+
+    #[test]
+    fn test_expand() {
+        let expand = |s: &str| {
+            expand_with(s, |name| match name {
+                "STARCRAFT_DIR" => Some(r"C:\StarCraft".to_string()),
+                "HOME" => Some("/home/bot".to_string()),
+                _ => None,
+            })
+        };
+        assert_eq!(
+            expand(r"%STARCRAFT_DIR%\maps\x.scx").unwrap(),
+            r"C:\StarCraft\maps\x.scx"
+        );
+        assert_eq!(expand("$HOME/bots").unwrap(), "/home/bot/bots");
+        assert_eq!(expand("${HOME}bots").unwrap(), "/home/botbots");
+        assert_eq!(expand("100%% $ 5%").unwrap(), "100%% $ 5%");
+        assert_eq!(expand("bot.dll").unwrap(), "bot.dll");
+        let err = expand("%MISSING%/bot.dll").unwrap_err().to_string();
+        assert!(err.contains("'MISSING'"), "{}", err);
+        assert!(expand("$MISSING").is_err());
+    }
+}
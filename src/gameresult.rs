@@ -0,0 +1,420 @@
+use std::fs::{read, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::bwapi::sanitize_character_name;
+use crate::{replay, Race};
+
+/// What the tournament module writes to `TM_LOG_RESULTS`
+#[derive(Deserialize, Debug, Default)]
+struct TmResult {
+    #[serde(default)]
+    is_winner: bool,
+    #[serde(default)]
+    is_crashed: bool,
+    #[serde(default)]
+    timed_out: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BotResult {
+    pub name: String,
+    /// The name of the bot in `bots.toml`, `name` is the one it played with
+    pub config_name: String,
+    pub race: Race,
+    /// The bot made it into the game (it logged at least one frame)
+    pub connected: bool,
+    /// The bot was in the game, but did not make it to the end
+    pub crashed: bool,
+    pub is_winner: bool,
+    pub frame_count: Option<u32>,
+    /// Frames the bot took longer than `slow_frame_ms` for, `None` without a frame log
+    pub slow_frames: Option<u32>,
+    pub replay: Option<PathBuf>,
+    /// How often the bot was relaunched before the game started (see `launch_retries`)
+    pub launch_retries: u8,
+    /// When the bot was launched, 0 is the first (see `shuffle_slots`). Without a human host, the
+    /// first one hosts. BWAPI doesn't tell the lobby slots, they might not follow this order.
+    pub launch_order: usize,
+    /// The BWAPI version the bot ran with, "bundled/unknown" for clients
+    pub bwapi_version: String,
+    #[serde(skip)]
+    timed_out: bool,
+}
+
+impl BotResult {
+    /// Collects the results the tournament module logged in `working_dir` (see `TM_LOG_*`),
+    /// and the replay BWAPI saved to `replay_dir`
+    pub fn collect(
+        name: String,
+        race: Race,
+        working_dir: &Path,
+        replay_dir: &Path,
+        started: SystemTime,
+        slow_frame_ms: u32,
+    ) -> Self {
+        let tm_dir = working_dir.join("tm");
+        let frame_count = last_frame(working_dir);
+        let tm_result = read(tm_dir.join("result.csv")).ok().and_then(|result| {
+            serde_json::from_slice::<TmResult>(&result)
+                .map_err(|e| warn!("Could not parse result of '{}': {}", name, e))
+                .ok()
+        });
+        let connected = frame_count.is_some() || tm_result.is_some();
+        Self {
+            connected,
+            // No result, but frames: The game did not end for this bot
+            crashed: tm_result
+                .as_ref()
+                .map(|it| it.is_crashed)
+                .unwrap_or(connected),
+            is_winner: tm_result.as_ref().map(|it| it.is_winner).unwrap_or(false),
+            timed_out: tm_result.as_ref().map(|it| it.timed_out).unwrap_or(false),
+            frame_count,
+            slow_frames: slow_frames(working_dir, slow_frame_ms),
+            replay: newest_replay(replay_dir, started),
+            launch_retries: 0,
+            launch_order: 0,
+            bwapi_version: String::new(),
+            config_name: name.clone(),
+            name,
+            race,
+        }
+    }
+}
+
+/// The last frame the tournament module logged in `working_dir` so far, works while the game runs
+pub fn last_frame(working_dir: &Path) -> Option<u32> {
+    let mut frames = File::open(working_dir.join("tm").join("frames.csv")).ok()?;
+    // One line per frame, only the end is interesting
+    let len = frames.metadata().ok()?.len();
+    frames.seek(SeekFrom::Start(len.saturating_sub(256))).ok()?;
+    let mut tail = vec![];
+    frames.read_to_end(&mut tail).ok()?;
+    String::from_utf8_lossy(&tail)
+        .lines()
+        .rev()
+        // Not the header
+        .find_map(|line| line.split(',').next()?.trim().parse().ok())
+}
+
+/// Frames the tournament module logged in `working_dir` that took the bot more than `threshold_ms`
+pub fn slow_frames(working_dir: &Path, threshold_ms: u32) -> Option<u32> {
+    let frames = std::fs::read_to_string(working_dir.join("tm").join("frames.csv")).ok()?;
+    Some(
+        frames
+            .lines()
+            // Not the header
+            .filter_map(|line| line.split(',').nth(1)?.trim().parse::<f64>().ok())
+            .filter(|&ms| ms > threshold_ms as f64)
+            .count() as u32,
+    )
+}
+
+/// Which limit stopped the game
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timeout {
+    /// `time_out_at_frame`
+    Frame,
+    /// `time_out_at_seconds`
+    WallClock,
+}
+
+/// When the phases of a game were over, counted from the start of launching it
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+    /// All bots were running (and clients connected)
+    pub launched: Duration,
+    /// A bot logged its first frame, `None` without a tournament module
+    pub started: Option<Duration>,
+    /// The last StarCraft exited
+    pub ended: Duration,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GameResult {
+    pub map: Option<String>,
+    /// The title stored inside the map, or its file name
+    pub map_name: Option<String>,
+    pub frame_count: Option<u32>,
+    /// The game was stopped by a timeout, it's a draw
+    pub timeout: Option<Timeout>,
+    pub winner: Option<String>,
+    pub losers: Vec<String>,
+    pub bots: Vec<BotResult>,
+    #[serde(skip)]
+    pub timings: Timings,
+}
+
+impl GameResult {
+    pub fn new(
+        map: Option<String>,
+        map_name: Option<String>,
+        time_out_at_frame: Option<u32>,
+        wall_clock_timed_out: bool,
+        bots: Vec<BotResult>,
+    ) -> Self {
+        let frame_count = bots.iter().filter_map(|bot| bot.frame_count).max();
+        let timeout = if bots.iter().any(|bot| bot.timed_out)
+            || matches!((frame_count, time_out_at_frame), (Some(frames), Some(limit)) if frames >= limit)
+        {
+            Some(Timeout::Frame)
+        } else if wall_clock_timed_out {
+            Some(Timeout::WallClock)
+        } else {
+            None
+        };
+        let winner = if timeout.is_some() {
+            None
+        } else {
+            bots.iter()
+                .find(|bot| bot.is_winner)
+                .map(|bot| bot.name.clone())
+        };
+        let losers = if winner.is_some() {
+            bots.iter()
+                .filter(|bot| !bot.is_winner)
+                .map(|bot| bot.name.clone())
+                .collect()
+        } else {
+            vec![]
+        };
+        Self {
+            map,
+            map_name,
+            frame_count,
+            timeout,
+            winner,
+            losers,
+            bots,
+            timings: Timings::default(),
+        }
+    }
+
+    /// Replaces each replay with a zip of it
+    pub fn compress_replays(&mut self) {
+        for bot in &mut self.bots {
+            // Missing if another instance of the bot saved the same replay
+            if let Some(rep) = bot.replay.as_ref().filter(|rep| rep.exists()) {
+                match replay::compress(rep) {
+                    Ok(zip) => {
+                        debug!("Compressed '{}'", rep.to_string_lossy());
+                        bot.replay = Some(zip);
+                    }
+                    Err(e) => warn!("{:#}", e),
+                }
+            }
+        }
+    }
+
+    /// Without a winner from the tournament module, looks at the replays of the bots: The winner
+    /// is the only one who didn't leave. Without replays, it's the bot whose StarCraft exited
+    /// last (`last_exited`), if there was one.
+    pub fn infer_winner(&mut self, last_exited: Option<&str>) {
+        if self.winner.is_some() || self.timeout.is_some() {
+            return;
+        }
+        let from_replay = self
+            .bots
+            .iter()
+            .filter_map(|bot| bot.replay.as_deref())
+            .find_map(|replay| {
+                let player = replay::winner(replay)
+                    .map_err(|e| debug!("{:#}", e))
+                    .ok()
+                    .flatten()?;
+                self.bot_of_player(&player)
+            });
+        let winner = match (from_replay, last_exited) {
+            (Some(winner), _) => {
+                debug!("'{}' won according to the replays", winner);
+                winner
+            }
+            (None, Some(winner)) => {
+                debug!("'{}' won, it left the game last", winner);
+                winner.to_string()
+            }
+            (None, None) => return,
+        };
+        for bot in self.bots.iter_mut() {
+            bot.is_winner = bot.name == winner;
+        }
+        self.losers = self
+            .bots
+            .iter()
+            .filter(|bot| !bot.is_winner)
+            .map(|bot| bot.name.clone())
+            .collect();
+        self.winner = Some(winner);
+    }
+
+    /// `bots.toml` name of the winner, tournaments need it when bots play under another name
+    pub fn winning_bot(&self) -> Option<&str> {
+        self.winner.as_ref()?;
+        self.bots
+            .iter()
+            .find(|bot| bot.is_winner)
+            .map(|bot| bot.config_name.as_str())
+    }
+
+    /// The bot playing as `player` in a replay, `None` if that's ambiguous
+    fn bot_of_player(&self, player: &str) -> Option<String> {
+        let mut bots = self
+            .bots
+            .iter()
+            .filter(|bot| bot.name == player || sanitize_character_name(&bot.name) == player);
+        match (bots.next(), bots.next()) {
+            (Some(bot), None) => Some(bot.name.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn write_result(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, self)?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+/// BWAPI puts replays in subfolders (see `save_replay`), find the one written after `since`
+fn newest_replay(dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+    for entry in dir.read_dir().ok()?.flatten() {
+        let path = entry.path();
+        let candidate = if path.is_dir() {
+            newest_replay(&path, since)
+                .and_then(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+        } else if path.extension().map(|ext| ext == "rep").unwrap_or(false) {
+            entry
+                .metadata()
+                .ok()
+                .and_then(|meta| meta.modified().ok())
+                .filter(|modified| *modified >= since)
+                .map(|modified| (modified, path))
+        } else {
+            None
+        };
+        if let Some(candidate) = candidate {
+            if newest.as_ref().map(|it| candidate.0 > it.0).unwrap_or(true) {
+                newest = Some(candidate);
+            }
+        }
+    }
+    newest.map(|(_, path)| path)
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::fs::{create_dir_all, write};
+
+    use crate::gameresult::{last_frame, slow_frames, BotResult, GameResult, Timeout};
+    use crate::replay::test::replay;
+    use crate::Race;
+
+    pub fn bot(name: &str, is_winner: bool, frame_count: Option<u32>) -> BotResult {
+        BotResult {
+            name: name.to_string(),
+            config_name: name.to_string(),
+            race: Race::Zerg,
+            connected: frame_count.is_some(),
+            crashed: false,
+            is_winner,
+            frame_count,
+            slow_frames: None,
+            replay: None,
+            launch_retries: 0,
+            launch_order: 0,
+            bwapi_version: "420".to_string(),
+            timed_out: false,
+        }
+    }
+
+    #[test]
+    fn test_last_frame() {
+        let working_dir = std::env::temp_dir().join("bwaishotgun-test-last-frame");
+        create_dir_all(working_dir.join("tm")).unwrap();
+        let frames: String = (0..1000).map(|frame| format!("{}, 42\n", frame)).collect();
+        write(
+            working_dir.join("tm/frames.csv"),
+            format!("frame, time\n{}1000, 42", frames),
+        )
+        .unwrap();
+        assert_eq!(last_frame(&working_dir), Some(1000));
+        write(working_dir.join("tm/frames.csv"), "frame, time\n").unwrap();
+        assert_eq!(last_frame(&working_dir), None);
+        assert_eq!(last_frame(&working_dir.join("missing")), None);
+    }
+
+    #[test]
+    fn test_slow_frames() {
+        let working_dir = std::env::temp_dir().join("bwaishotgun-test-slow-frames");
+        create_dir_all(working_dir.join("tm")).unwrap();
+        write(
+            working_dir.join("tm/frames.csv"),
+            "frame, time\n0, 12\n1, 55\n2, 56.5\n3, 1200\n4, 30",
+        )
+        .unwrap();
+        assert_eq!(slow_frames(&working_dir, 55), Some(2));
+        assert_eq!(slow_frames(&working_dir, 2000), Some(0));
+        assert_eq!(slow_frames(&working_dir.join("missing"), 55), None);
+    }
+
+    #[test]
+    fn test_winner_and_timeout() {
+        let result = GameResult::new(
+            None,
+            None,
+            Some(1000),
+            false,
+            vec![bot("A", true, Some(500)), bot("B", false, Some(500))],
+        );
+        assert_eq!(result.winner.as_deref(), Some("A"));
+        assert_eq!(result.losers, vec!["B".to_string()]);
+        assert_eq!(result.timeout, None);
+
+        let result = GameResult::new(
+            None,
+            None,
+            Some(1000),
+            false,
+            vec![bot("A", true, Some(1000)), bot("B", false, None)],
+        );
+        assert_eq!(result.timeout, Some(Timeout::Frame));
+        assert_eq!(result.winner, None);
+        assert!(result.losers.is_empty());
+        assert!(!result.bots[1].connected);
+
+        let result = GameResult::new(None, None, None, true, vec![bot("A", false, None)]);
+        assert_eq!(result.timeout, Some(Timeout::WallClock));
+        assert_eq!(result.winner, None);
+    }
+
+    #[test]
+    fn test_infer_winner() {
+        let dir = std::env::temp_dir().join("bwaishotgun-test-infer-winner");
+        create_dir_all(&dir).unwrap();
+        let rep = dir.join("game.rep");
+        let leave: &[u8] = &[0x57, 1];
+        write(&rep, replay(&[(0, "A"), (1, "B")], &[(100, 0, leave)])).unwrap();
+        let bots = || vec![bot("A", false, Some(500)), bot("B", false, Some(500))];
+
+        let mut with_replay = bots();
+        with_replay[0].replay = Some(rep);
+        let mut result = GameResult::new(None, None, None, false, with_replay);
+        result.infer_winner(Some("A"));
+        assert_eq!(result.winner.as_deref(), Some("B"));
+        assert_eq!(result.losers, vec!["A".to_string()]);
+        assert!(result.bots[1].is_winner);
+
+        let mut result = GameResult::new(None, None, None, false, bots());
+        result.infer_winner(Some("A"));
+        assert_eq!(result.winner.as_deref(), Some("A"));
+        let mut result = GameResult::new(None, None, None, false, bots());
+        result.infer_winner(None);
+        assert_eq!(result.winner, None);
+    }
+}
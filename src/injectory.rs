@@ -1,15 +1,26 @@
-use std::fs::{copy, create_dir_all, File};
+use std::fs::{copy, create_dir_all};
 use std::process::Command;
 
 use anyhow::ensure;
 
 use crate::botsetup::{BotSetup, LaunchBuilder};
-use crate::{tools_folder, AutoMenu, BwapiConnectMode, BwapiIni, BwapiLanMode, GameConfig};
+use crate::map::validate_map;
+use crate::{
+    tool, tools_folder, AutoMenu, BwapiConnectMode, BwapiIni, BwapiLanMode, GameConfig, Race,
+    INJECTORY_EXE,
+};
 
+#[derive(Debug)]
 pub enum InjectoryConnectMode {
     Host {
         map: Option<String>,
-        player_count: usize,
+        min_players: usize,
+        max_players: usize,
+    },
+    /// Play against the computer, no other bots involved
+    SinglePlayer {
+        map: String,
+        computer_races: Vec<Race>,
     },
     Join,
 }
@@ -19,13 +30,55 @@ pub struct Injectory {
     pub game_name: String,
     pub connect_mode: InjectoryConnectMode,
     pub wmode: bool,
-    pub sound: bool,
-    pub game_speed: i32,
+    /// BWAPI's own window of this size, instead of WMode
+    pub resolution: Option<(u32, u32)>,
     pub lan_mode: BwapiLanMode,
 }
 
 impl LaunchBuilder for Injectory {
-    fn build_command(&self, _game_config: &GameConfig) -> anyhow::Result<Command> {
+    fn bwapi_ini(&self) -> BwapiIni {
+        BwapiIni {
+            auto_menu: match &self.connect_mode {
+                InjectoryConnectMode::Host {
+                    map,
+                    min_players,
+                    max_players,
+                } => AutoMenu::Lan {
+                    name: self.bot_setup.character_name(),
+                    game_name: self.game_name.clone(),
+                    race: self.bot_setup.lan_race(),
+                    connect_mode: BwapiConnectMode::Host {
+                        map: map.clone(),
+                        min_players: *min_players,
+                        max_players: *max_players,
+                    },
+                    lan_mode: self.lan_mode,
+                    auto_restart: self.bot_setup.auto_restart,
+                },
+                InjectoryConnectMode::SinglePlayer {
+                    map,
+                    computer_races,
+                } => AutoMenu::SinglePlayer {
+                    name: self.bot_setup.character_name(),
+                    race: self.bot_setup.race,
+                    map: map.clone(),
+                    computer_races: computer_races.clone(),
+                },
+                InjectoryConnectMode::Join => AutoMenu::Lan {
+                    name: self.bot_setup.character_name(),
+                    game_name: self.game_name.clone(),
+                    race: self.bot_setup.lan_race(),
+                    connect_mode: BwapiConnectMode::Join,
+                    lan_mode: self.lan_mode,
+                    auto_restart: self.bot_setup.auto_restart,
+                },
+            },
+            window_size: self.resolution,
+            ..BwapiIni::from(&self.bot_setup)
+        }
+    }
+
+    fn build_command(&self, game_config: &GameConfig) -> anyhow::Result<Command> {
         ensure!(
             self.bot_setup.starcraft_exe.exists(),
             "Could not find 'StarCraft.exe'"
@@ -42,56 +95,25 @@ impl LaunchBuilder for Injectory {
             "Could not find '{}'",
             bwapi_dll.to_string_lossy()
         );
-        let injectory = tools_folder().join("injectory_x86.exe");
+        let injectory = tool(game_config.injectory_path.as_deref(), INJECTORY_EXE);
         ensure!(
             injectory.exists(),
             r"Could not find '{}'. Please make sure to extract all files, or check your antivirus software.",
-            tools_folder().to_string_lossy()
+            injectory.to_string_lossy()
         );
-        let bwapi_ini = bwapi_data.join("bwapi.ini");
-        let mut bwapi_ini_file = File::create(&bwapi_ini)?;
-        BwapiIni {
-            auto_menu: match &self.connect_mode {
-                InjectoryConnectMode::Host { map, player_count } => AutoMenu::AutoMenu {
-                    name: self.bot_setup.player_name.clone(),
-                    game_name: self.game_name.clone(),
-                    race: self.bot_setup.race,
-                    connect_mode: BwapiConnectMode::Host {
-                        map: map.clone(),
-                        player_count: *player_count,
-                    },
-                    lan_mode: self.lan_mode,
-                },
-                InjectoryConnectMode::Join => AutoMenu::AutoMenu {
-                    name: self.bot_setup.player_name.clone(),
-                    game_name: self.game_name.clone(),
-                    race: self.bot_setup.race,
-                    connect_mode: BwapiConnectMode::Join,
-                    lan_mode: self.lan_mode,
-                },
-            },
-            game_speed: self.game_speed,
-            sound: self.sound,
-            tm_module: self.bot_setup.tournament_module.clone(),
-            ..BwapiIni::from(&self.bot_setup)
-        }
-        .write(&mut bwapi_ini_file)?;
-
         // BWAPI will look for the map in the "bot" folder, not in the starcraft path, so we'll copy the map over.
         // We really need to copy, because it will open the map to check for settings.
         // One caveat: BWAPI does not allow game speed selection, so this might host with an invalid game speed
         if let InjectoryConnectMode::Host { map: Some(map), .. } = &self.connect_mode {
             let original_map = self.bot_setup.starcraft_path.join(map);
-            ensure!(
-                original_map.exists(),
-                "Map '{}' does not exist",
-                original_map.to_string_lossy()
-            );
+            validate_map(&original_map)?;
             let tmp_map = self.bot_setup.bot_base_path.join(map);
             create_dir_all(tmp_map.parent().expect("Map file has no parent directory"))?;
             copy(original_map, tmp_map)?;
         }
 
+        let bwapi_ini = self.bot_setup.write_bwapi_ini(&self.bwapi_ini())?;
+
         let mut cmd = self.bot_setup.sandbox.wrap_executable(injectory);
         cmd.arg("-l").arg(&self.bot_setup.starcraft_exe);
         cmd.arg("-i")
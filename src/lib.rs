@@ -0,0 +1,3033 @@
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::fs::{create_dir_all, metadata, read, remove_dir_all, remove_file, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use clap::Parser;
+use log::{debug, info, trace, warn};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use retry::{retry, OperationResult};
+use serde::de::Unexpected;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
+
+use crate::botsetup::{Binary, BotSetup, LaunchBuilder};
+use crate::bwapi::{
+    sanitize_character_name, AutoMenu, BwapiConnectMode, BwapiIni, GameTableAccess,
+    DEFAULT_REPLAY_TEMPLATE, MAX_CHARACTER_NAME_LEN, MAX_GAME_NAME_LEN,
+};
+use crate::bwheadless::{BwHeadless, BwHeadlessConnectMode};
+use crate::cli::Cli;
+use crate::gameresult::{BotResult, Timings};
+use crate::injectory::{Injectory, InjectoryConnectMode};
+use crate::openbw::{OpenBw, OpenBwConnectMode};
+use crate::sandbox::SandboxMode;
+use crate::setup::StarCraftInstallation;
+
+mod bench;
+mod botsetup;
+mod bwapi;
+mod bwheadless;
+mod check;
+mod cli;
+mod envvars;
+mod gameresult;
+mod injectory;
+mod map;
+mod mpq;
+mod openbw;
+mod process;
+mod replay;
+mod sandbox;
+mod setup;
+mod tournament;
+mod watch;
+mod window;
+
+pub use crate::bwapi::{
+    free_slot_count, occupied_pids, BwapiLanMode, BwapiVersion, GameSpeed, WaitConfig,
+};
+pub use crate::gameresult::{GameResult, Timeout};
+
+#[derive(Deserialize, Debug, Default)]
+struct ShotgunConfig {
+    #[serde(default)]
+    starcraft_path: StarCraftInstallation,
+    java_path: Option<String>,
+    #[serde(default)]
+    sandbox: SandboxMode,
+    /// Name of BWAPI's shared memory game table, if not `Local\bwapi_shared_memory_game_list`
+    game_table_os_id: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum HeadfulMode {
+    Off,
+    On {
+        #[serde(default)]
+        no_wmode: bool,
+        #[serde(default)]
+        no_sound: bool,
+        /// Size of BWAPI's window (ie. `[1280, 960]`, scaling the game), instead of StarCraft's
+        /// 640x480 with WMode
+        #[serde(default)]
+        resolution: Option<(u32, u32)>,
+    },
+    /// With a window (in windowed mode), that is minimized right away to not steal the focus.
+    /// Unlike `Off`, StarCraft still renders the game.
+    Minimized {
+        #[serde(default)]
+        no_sound: bool,
+    },
+}
+
+impl Default for HeadfulMode {
+    fn default() -> Self {
+        Self::Off
+    }
+}
+
+/// Priority class of StarCraft and the bot (there's no `Realtime`, it would starve the system)
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessPriority {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StarcraftFlavor {
+    /// StarCraft 1.16.1, started with bwheadless or injectory
+    Retail,
+    /// OpenBW's `BWAPILauncher`, `starcraft_path` has to point to its folder (with the MPQs of StarCraft)
+    OpenBW,
+}
+
+impl Default for StarcraftFlavor {
+    fn default() -> Self {
+        Self::Retail
+    }
+}
+
+impl StarcraftFlavor {
+    /// What will be launched for each bot
+    pub fn executable(&self, starcraft_path: &Path) -> PathBuf {
+        match self {
+            Self::Retail => starcraft_path.join("StarCraft.exe"),
+            Self::OpenBW => {
+                starcraft_path.join(format!("BWAPILauncher{}", std::env::consts::EXE_SUFFIX))
+            }
+        }
+    }
+
+    /// OpenBW connects clients on its own, there's no game table to wait on
+    pub fn uses_game_table(&self) -> bool {
+        matches!(self, Self::Retail)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BotLaunchConfig {
+    pub name: String,
+    pub player_name: Option<String>,
+    pub race: Option<Race>,
+    #[serde(default)]
+    pub headful: HeadfulMode,
+    /// Run the `debug_executable` of the bot instead (ie. to attach a debugger)
+    #[serde(default)]
+    pub debug: bool,
+    /// Cores (starting at 0) StarCraft and the bot may run on, for steadier frame times
+    pub cpu_affinity: Option<Vec<usize>>,
+    pub priority: Option<ProcessPriority>,
+    /// Passed to Java before `-jar` (ie. `-Xmx2g`), only for JAR bots
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    /// A referee or observer bot, the host doesn't wait for it to start the game. There are no
+    /// observer slots in melee games: it joins as Random and takes a start location like a player.
+    #[serde(default)]
+    pub observer: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum GameType {
+    Melee(Vec<BotLaunchConfig>),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct GameConfig {
+    pub map: Option<String>,
+    /// Maps or folders of maps (relative to StarCraft), one of them is picked for the game
+    #[serde(default)]
+    pub map_pool: Vec<PathBuf>,
+    /// Seed for random picks (ie. map and race), if unset they will differ each game
+    pub seed: Option<u64>,
+    pub game_name: Option<String>,
+    pub game_type: GameType,
+    #[serde(default)]
+    pub starcraft_flavor: StarcraftFlavor,
+    /// The game is hosted outside of BWAIShotgun, all bots will join (`game_name` or the first game found)
+    #[serde(default)]
+    pub human_host: bool,
+    /// Start bot-hosted games once this many players joined, instead of waiting for all of them
+    pub min_players: Option<usize>,
+    /// Players the host waits for at most (default: the number of bots), ie. to leave room for humans
+    pub max_players: Option<usize>,
+    /// Let the only bot play against the computer in a single player game (needs a StarCraft window)
+    #[serde(default)]
+    pub vs_computer: bool,
+    /// Race of each computer opponent with `vs_computer` (ie. `['Terran', 'Zerg']`), by default one
+    /// computer of the map's default race
+    #[serde(default)]
+    pub computer_races: Vec<Race>,
+    #[serde(default)]
+    pub human_speed: bool,
+    /// `speed_override` of BWAPI (a preset or ms per frame), can't be combined with `human_speed`
+    pub game_speed: Option<GameSpeed>,
+    /// Play sounds (only audible in headful mode), unset leaves it to the `headful` setting of each
+    /// bot (`no_sound`)
+    pub sound: Option<bool>,
+    /// Frames between issuing and executing commands, more gives slow bots more time per frame
+    #[serde(default = "default_latency")]
+    pub latency_frames: u32,
+    pub lan_mode: Option<BwapiLanMode>,
+    pub time_out_at_frame: Option<u32>,
+    /// Stop the game after this many seconds, even if no frames are advancing
+    pub time_out_at_seconds: Option<u64>,
+    /// `save_replay` template of BWAPI, an empty template disables replays
+    pub replay_template: Option<String>,
+    /// Saves replays to a folder per bot in this folder, instead of the bot folders
+    pub replay_root: Option<PathBuf>,
+    /// Only write the BWAPI.ini of each bot, without starting any games
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Let BWAPI start another game after each game (only for bots managing the menus themselves, ie. headful bots)
+    #[serde(default)]
+    pub auto_restart: bool,
+    /// Keep the files written for a game (ie. the BWAPI.ini of each bot) after it ended
+    #[serde(default)]
+    pub keep_artifacts: bool,
+    /// Leave StarCraft windows open when the game ends (or times out) until they are closed
+    #[serde(default)]
+    pub keep_open: bool,
+    /// Zip the replays once the game is over, to save disk space
+    #[serde(default)]
+    pub compress_replays: bool,
+    /// Frames a bot takes longer than this (in ms) count as slow frames in the result
+    #[serde(default = "default_slow_frame_ms")]
+    pub slow_frame_ms: u32,
+    /// Fail instead of warning when StarCraft doesn't match the BWAPI version of a bot, or a bot
+    /// has an unknown BWAPI.dll
+    #[serde(default)]
+    pub strict: bool,
+    /// Treat the BWAPI.dll of all bots as this version, ie. for builds with an unknown CRC
+    pub bwapi_version: Option<BwapiVersion>,
+    /// Pick a concrete race for bots playing Random, instead of letting BWAPI pick one
+    #[serde(default)]
+    pub roll_random_race: bool,
+    /// Launch the bots in a random order, the host and the lobby slots change (clients still come
+    /// first). Not for series, they alternate the host on their own.
+    #[serde(default)]
+    pub shuffle_slots: bool,
+    /// Waiting for BWAPI servers to open slots and for clients to connect
+    #[serde(default)]
+    pub wait: WaitConfig,
+    /// Relaunch client bots that die or don't connect before the game starts up to this many times
+    #[serde(default)]
+    pub launch_retries: u8,
+    /// Only pick maps of the map pool for exactly as many players as are in the game (ie. 2 player
+    /// maps for 1v1), not all that have enough start locations
+    #[serde(default)]
+    pub exact_map_players: bool,
+    /// Custom build of injectory, instead of the bundled `tools/injectory_x86.exe`
+    pub injectory_path: Option<PathBuf>,
+    /// Custom build of bwheadless, instead of the bundled `tools/bwheadless.exe`
+    pub bwheadless_path: Option<PathBuf>,
+}
+
+/// How long bots get to leave the game once `time_out_at_frame` is reached, before it's stopped
+const FRAME_TIME_OUT_GRACE: Duration = Duration::from_secs(10);
+
+/// Affinity masks are 32 bit for StarCraft (and winapi's `SetProcessAffinityMask`)
+const MAX_CPU_CORES: usize = 32;
+
+pub const DEFAULT_LATENCY_FRAMES: u32 = 3;
+/// Anything above makes games unbearably laggy
+pub const MAX_LATENCY_FRAMES: u32 = 12;
+
+fn default_latency() -> u32 {
+    DEFAULT_LATENCY_FRAMES
+}
+
+/// The frame time limit of the AIIDE and SSCAIT rules
+pub const DEFAULT_SLOW_FRAME_MS: u32 = 55;
+
+fn default_slow_frame_ms() -> u32 {
+    DEFAULT_SLOW_FRAME_MS
+}
+
+impl GameConfig {
+    fn load(path: &Path) -> anyhow::Result<GameConfig> {
+        toml::from_slice(
+            read(path)
+                .with_context(|| format!("Could not read '{}'", path.to_string_lossy()))?
+                .as_slice(),
+        )
+        .with_context(|| format!("'{}' is invalid", path.to_string_lossy()))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.human_host
+                || matches!(&self.map, Some(s) if !s.is_empty())
+                || !self.map_pool.is_empty(),
+            "Map must be set for bot-hosted games"
+        );
+        let GameType::Melee(bots) = &self.game_type;
+        for bot in bots {
+            if let HeadfulMode::On {
+                resolution: Some((width, height)),
+                ..
+            } = bot.headful
+            {
+                ensure!(
+                    width > 0 && height > 0,
+                    "The window of '{}' can't be {}x{}",
+                    bot.name,
+                    width,
+                    height
+                );
+            }
+            if let Some(cores) = &bot.cpu_affinity {
+                ensure!(
+                    !cores.is_empty() && cores.iter().all(|&core| core < MAX_CPU_CORES),
+                    "The 'cpu_affinity' of '{}' must list cores between 0 and {}",
+                    bot.name,
+                    MAX_CPU_CORES - 1
+                );
+            }
+        }
+        if let Some(game_name) = &self.game_name {
+            ensure!(
+                !game_name.trim().is_empty()
+                    && game_name.len() <= MAX_GAME_NAME_LEN
+                    && game_name.chars().all(|c| c.is_ascii_graphic() || c == ' '),
+                "The game name '{}' must be 1 to {} characters (letters, digits, spaces and punctuation)",
+                game_name,
+                MAX_GAME_NAME_LEN
+            );
+        }
+        ensure!(
+            self.min_players.unwrap_or(1) >= 1 && self.max_players.unwrap_or(8) <= 8,
+            "Games are for 1 to 8 players"
+        );
+        if let (Some(min_players), Some(max_players)) = (self.min_players, self.max_players) {
+            ensure!(
+                min_players <= max_players,
+                "'min_players' ({}) must not be more than 'max_players' ({})",
+                min_players,
+                max_players
+            );
+        }
+        ensure!(
+            !self.keep_open
+                || bots
+                    .iter()
+                    .any(|bot| !matches!(bot.headful, HeadfulMode::Off)),
+            "'keep_open' needs a bot with a StarCraft window (see '--headful')"
+        );
+        if self.vs_computer {
+            ensure!(
+                bots.len() == 1 && !self.human_host,
+                "Only a single bot can play against the computer, without a human host"
+            );
+        }
+        ensure!(
+            bots.is_empty() || bots.iter().any(|bot| !bot.observer),
+            "All bots are observers, nobody would play"
+        );
+        ensure!(
+            !self.vs_computer || bots.iter().all(|bot| !bot.observer),
+            "Observers can't play against the computer"
+        );
+        ensure!(
+            !self.wait.wait_all_ready || self.min_players.is_none(),
+            "'min_players' can't be combined with 'wait_all_ready', the host waits for all bots"
+        );
+        ensure!(
+            self.vs_computer || self.computer_races.is_empty(),
+            "'computer_races' are only used with 'vs_computer'"
+        );
+        ensure!(
+            self.computer_races.len() <= 7,
+            "There can be at most 7 computer players"
+        );
+        ensure!(
+            !(self.human_speed && self.game_speed.is_some()),
+            "Either set 'human_speed' or 'game_speed', not both"
+        );
+        ensure!(
+            (1..=MAX_LATENCY_FRAMES).contains(&self.latency_frames),
+            "'latency_frames' must be between 1 and {}, but is {}",
+            MAX_LATENCY_FRAMES,
+            self.latency_frames
+        );
+        ensure!(
+            !self.wait.poll_interval.is_zero(),
+            "'poll_interval_ms' must be greater than 0"
+        );
+        Ok(())
+    }
+
+    /// Validates the config and resolves what doesn't change between games
+    fn prepare(&mut self, starcraft_path: &Path) -> anyhow::Result<()> {
+        self.validate()?;
+        self.resolve_map(starcraft_path)?;
+        let starcraft_exe = self.starcraft_flavor.executable(starcraft_path);
+        ensure!(
+            starcraft_exe.exists(),
+            "Could not locate '{}' in configured location: '{}'",
+            starcraft_exe
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy(),
+            starcraft_path.to_string_lossy()
+        );
+        for (custom, bundled) in [
+            (&self.injectory_path, INJECTORY_EXE),
+            (&self.bwheadless_path, BWHEADLESS_EXE),
+        ] {
+            if let Some(custom) = custom {
+                let crc = BwapiVersion::dll_crc(custom)
+                    .with_context(|| format!("Could not read '{}'", custom.to_string_lossy()))?;
+                if BwapiVersion::dll_crc(&tools_folder().join(bundled)).ok() == Some(crc) {
+                    warn!(
+                        "'{}' is the same as the bundled '{}'",
+                        custom.to_string_lossy(),
+                        bundled
+                    );
+                } else {
+                    info!(
+                        "Using '{}' (CRC 0x{:08X}) instead of the bundled '{}'",
+                        custom.to_string_lossy(),
+                        crc,
+                        bundled
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the map in StarCraft's `maps` folder first, then in StarCraft itself
+    fn resolve_map(&mut self, starcraft_path: &Path) -> anyhow::Result<()> {
+        self.map_pool = self
+            .map_pool
+            .iter()
+            .map(|maps| envvars::expand(&maps.to_string_lossy()).map(PathBuf::from))
+            .collect::<anyhow::Result<_>>()?;
+        if let Some(map) = self.map.as_deref().filter(|map| !map.is_empty()) {
+            let resolved = map::resolve_map(starcraft_path, &envvars::expand(map)?)?;
+            debug!("Resolved map '{}' to '{}'", map, resolved.to_string_lossy());
+            self.map = Some(resolved.to_string_lossy().to_string());
+        }
+        Ok(())
+    }
+
+    /// Replaces the map with a random one of the map pool (if there is one)
+    fn resolve_map_pool(
+        &mut self,
+        starcraft_path: &Path,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<()> {
+        if self.map_pool.is_empty() {
+            return Ok(());
+        }
+        let GameType::Melee(bots) = &self.game_type;
+        // Computers need start locations as well, humans joining the host too
+        let computers = if self.vs_computer {
+            self.computer_races.len().max(1)
+        } else {
+            0
+        };
+        let players = (bots.len() + computers).max(self.max_players.unwrap_or(0));
+        let map = map::pick_map(
+            starcraft_path,
+            &self.map_pool,
+            players,
+            self.exact_map_players,
+            rng,
+        )?;
+        info!("Picked map '{}'", map.to_string_lossy());
+        self.map = Some(map.to_string_lossy().to_string());
+        Ok(())
+    }
+
+    /// Without a `game_name`, bot-hosted games get a unique one to not collide with other games in the LAN
+    fn resolve_game_name(&mut self, rng: &mut impl Rng) {
+        if self.game_name.is_none() && !self.human_host {
+            let game_name = format!("shotgun-{:06x}", rng.gen_range(0..0x1000000));
+            info!("Game name: '{}'", game_name);
+            self.game_name = Some(game_name);
+        }
+    }
+}
+
+/// The settings that matter most, for a quick look (see `--verbose`)
+impl Display for GameConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.map.as_deref().filter(|map| !map.is_empty()) {
+            Some(map) => writeln!(f, "Map: {}", map)?,
+            None if !self.map_pool.is_empty() => {
+                writeln!(f, "Map: one of {} in the map pool", self.map_pool.len())?
+            }
+            None => writeln!(f, "Map: -")?,
+        }
+        let speed = match self.game_speed {
+            Some(GameSpeed::Millis(millis)) => format!("{}ms per frame", millis),
+            Some(speed) => format!("{:?}", speed),
+            None if self.human_speed => "Human".to_string(),
+            None => "BWAPI's default".to_string(),
+        };
+        writeln!(
+            f,
+            "Game: {}{}, {:?}, {}, {} latency frames, speed {}",
+            if self.human_host {
+                "hosted by a human"
+            } else {
+                "hosted by a bot"
+            },
+            self.game_name
+                .as_deref()
+                .map(|name| format!(" as '{}'", name))
+                .unwrap_or_default(),
+            self.starcraft_flavor,
+            self.lan_mode.unwrap_or(BwapiLanMode::LocalPC),
+            self.latency_frames,
+            speed
+        )?;
+        let limit = |limit: Option<String>| limit.unwrap_or_else(|| "-".to_string());
+        writeln!(
+            f,
+            "Timeouts: frame {}, {} seconds",
+            limit(self.time_out_at_frame.map(|it| it.to_string())),
+            limit(self.time_out_at_seconds.map(|it| it.to_string()))
+        )?;
+        let GameType::Melee(bots) = &self.game_type;
+        write!(f, "Bots:")?;
+        for bot in bots {
+            let race = bot
+                .race
+                .map(|race| race.to_string())
+                .unwrap_or_else(|| "race of bot.toml".to_string());
+            let headful = match bot.headful {
+                HeadfulMode::Off => "",
+                HeadfulMode::On { .. } => ", headful",
+                HeadfulMode::Minimized { .. } => ", minimized",
+            };
+            write!(f, "\n  {} ({}{})", bot.name, race, headful)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub enum TournamentModule {
+    None,
+    Default,
+    Custom { prefix: String },
+}
+
+impl Default for TournamentModule {
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
+impl TournamentModule {
+    /// Finds the tournament module for `bwapi_version` in the `tm` folder, `None` if there is none to use
+    pub fn resolve(&self, bwapi_version: &BwapiVersion) -> anyhow::Result<Option<PathBuf>> {
+        let prefix = match self {
+            TournamentModule::None => return Ok(None),
+            // Same case as the files, in case the file system cares
+            TournamentModule::Default => "TM",
+            TournamentModule::Custom { prefix } => prefix,
+        };
+        let prefix = envvars::expand(prefix)?;
+        let version = match bwapi_version.tm_version_short() {
+            Some(version) => version,
+            None => {
+                info!("Custom BWAPI.dll detected, not adding TM module");
+                return Ok(None);
+            }
+        };
+        let tm = base_folder()
+            .join("tm")
+            .join(format!("{}_{}.dll", prefix, version));
+        ensure!(
+            tm.is_file(),
+            "Could not find tournament module '{}'",
+            tm.to_string_lossy()
+        );
+        // A TM built for another BWAPI version crashes the bot
+        let crc = BwapiVersion::dll_crc(&tm)
+            .with_context(|| format!("Could not check '{}'", tm.to_string_lossy()))?;
+        match BwapiVersion::from_tm_crc(crc) {
+            Some(tm_version) => ensure!(
+                tm_version.tm_version_short() == Some(version),
+                "'{}' is the tournament module of BWAPI {}, not {}",
+                tm.to_string_lossy(),
+                tm_version.version_short(),
+                version
+            ),
+            None if matches!(self, TournamentModule::Default) => warn!(
+                "Unknown tournament module '{}', make sure it was built for BWAPI {}",
+                tm.to_string_lossy(),
+                version
+            ),
+            None => (),
+        }
+        Ok(Some(tm))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct BotDefinition {
+    race: Race,
+    executable: Option<String>,
+    /// Debug build of a DLL bot, written as `ai_dbg` or used instead of `executable` with `--debug-bot`
+    debug_executable: Option<String>,
+    #[serde(default)]
+    tournament_module: TournamentModule,
+    /// Keep the settings of the bot's own `bwapi-data/bwapi.ini`, only `[ai]` and `[auto_menu]`
+    /// are replaced
+    #[serde(default)]
+    merge_bwapi_ini: bool,
+}
+
+impl BotDefinition {
+    /// The configured `executable` of the bot in `path`, or whatever is in `bwapi-data/AI`
+    fn binary(&self, path: &Path) -> anyhow::Result<Binary> {
+        let executable = self
+            .executable
+            .as_deref()
+            .map(envvars::expand)
+            .transpose()?;
+        let bot_binary = executable.as_deref().and_then(|s| {
+            // First try from bot path
+            Binary::from_path(path.join(s).as_path())
+                // Then from base path
+                .or_else(|| Binary::from_path(base_folder().join(s).as_path()))
+        });
+        if let Some(bot_binary) = bot_binary {
+            return Ok(bot_binary);
+        }
+        // Workaround BWAPI 3.7.x "strangeness" of removing ":" ...
+        let bwapi_data_path = path.join("bwapi-data");
+        let mut ai_module_path = bwapi_data_path.components();
+        ai_module_path.next();
+        let ai_module_path = ai_module_path.as_path().join("AI");
+        // Lastly search
+        Binary::search(ai_module_path.as_path())
+            .context("Could not find bot binary in 'bwapi-data/AI'")
+    }
+
+    /// The configured `executable` if it's a zipped bot (see `Binary::unzip`)
+    fn zip(&self, path: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let executable = match self
+            .executable
+            .as_deref()
+            .map(envvars::expand)
+            .transpose()?
+        {
+            Some(executable) if executable.to_lowercase().ends_with(".zip") => executable,
+            _ => return Ok(None),
+        };
+        // Like `binary`: From the bot path, then from the base path
+        let zip = path.join(&executable);
+        Ok(Some(if zip.is_file() {
+            zip
+        } else {
+            base_folder().join(&executable)
+        }))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Race {
+    Protoss,
+    Terran,
+    Zerg,
+    Random,
+}
+
+impl Race {
+    /// Rolls a concrete race for `Random`, other races are returned as is
+    pub fn resolve(self, rng: &mut impl Rng) -> Race {
+        match self {
+            Race::Random => match rng.gen_range(0..3) {
+                0 => Race::Protoss,
+                1 => Race::Terran,
+                _ => Race::Zerg,
+            },
+            race => race,
+        }
+    }
+}
+
+/// Accepted by `Race::from_str`, case-insensitive
+const RACE_TOKENS: &str = "one of Zerg/Protoss/Terran/Random or z/p/t/r";
+
+impl FromStr for Race {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "r" | "random" => Ok(Race::Random),
+            "p" | "protoss" => Ok(Race::Protoss),
+            "z" | "zerg" => Ok(Race::Zerg),
+            "t" | "terran" => Ok(Race::Terran),
+            _ => bail!("Invalid race '{}', expected {}", s, RACE_TOKENS),
+        }
+    }
+}
+
+impl<'d> Deserialize<'d> for Race {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'d>,
+    {
+        let race = String::deserialize(deserializer)?;
+        race.parse()
+            .map_err(|_| serde::de::Error::invalid_value(Unexpected::Str(&race), &RACE_TOKENS))
+    }
+}
+
+impl Serialize for Race {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl Display for Race {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Race::Protoss => "Protoss",
+                Race::Terran => "Terran",
+                Race::Zerg => "Zerg",
+                Race::Random => "Random",
+            }
+        )
+    }
+}
+
+/// bwaishotgun base folder
+pub fn base_folder() -> PathBuf {
+    std::env::current_exe()
+        .expect("Could not find executable")
+        .parent()
+        .expect("BWAIShotgun folder does not exist")
+        .to_owned()
+}
+
+/// tools folder
+pub fn tools_folder() -> PathBuf {
+    base_folder().join("tools")
+}
+pub const INJECTORY_EXE: &str = "injectory_x86.exe";
+pub const BWHEADLESS_EXE: &str = "bwheadless.exe";
+/// The `custom` build of a helper, or the bundled one
+pub fn tool(custom: Option<&Path>, bundled: &str) -> PathBuf {
+    custom
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| tools_folder().join(bundled))
+}
+pub fn internal_scbw_folder() -> PathBuf {
+    base_folder().join("scbw")
+}
+
+pub fn download_folder() -> anyhow::Result<PathBuf> {
+    let download_folder = base_folder().join("download");
+    create_dir_all(&download_folder)?;
+    Ok(download_folder)
+}
+
+pub struct BotProcess {
+    name: String,
+    bwheadless: Child,
+    bot: Option<Child>,
+    /// StarCraft has a window, that stays open with `keep_open`
+    headful: bool,
+    /// Neither wins nor loses
+    observer: bool,
+}
+
+impl BotProcess {
+    /// Why to stop waiting for this bot's StarCraft (see `GameTableAccess::poll`)
+    fn gave_up(&mut self) -> Option<&'static str> {
+        if interrupted() {
+            Some("Interrupted")
+        } else if !matches!(self.bwheadless.try_wait(), Ok(None)) {
+            Some("BWAPI process died")
+        } else {
+            None
+        }
+    }
+
+    /// Why bwheadless/injectory exited, `None` while it's running
+    fn exit_reason(&mut self) -> Option<String> {
+        match self.bwheadless.try_wait() {
+            Ok(Some(status)) => Some(describe_exit_status(status)),
+            _ => None,
+        }
+    }
+}
+
+/// Explains the common exit codes of bwheadless & co, which are mostly Windows' `NTSTATUS` codes of
+/// StarCraft failing to start
+fn describe_exit_status(status: ExitStatus) -> String {
+    let code = match status.code() {
+        Some(code) => code,
+        None => return "was killed".to_string(),
+    };
+    let reason = match code as u32 {
+        0 => "exited",
+        1 => "failed (see 'game_err.log' in the logs of the bot)",
+        0xC0000005 => "crashed (access violation)",
+        0xC000007B => "could not load a DLL, it might be a 64 bit DLL",
+        0xC0000135 => "could not find a DLL (is the Visual C++ runtime installed?)",
+        0xC0000142 => "could not initialize a DLL",
+        0xC000013A => "was stopped by ctrl+c",
+        0xC0000409 => "crashed (stack buffer overrun)",
+        _ => "failed",
+    };
+    format!("{} with exit code {} (0x{:08X})", reason, code, code)
+}
+
+impl Drop for BotProcess {
+    fn drop(&mut self) {
+        // Killing already exited processes does no harm
+        if let Some(bot) = &mut self.bot {
+            bot.kill().ok();
+        }
+        self.bwheadless.kill().ok();
+    }
+}
+
+/// Copies the output of a bot to `log` line by line, and to the log of BWAIShotgun (at debug level).
+/// Stops once the bot closed its end, `log` is unbuffered so nothing is lost if the bot is killed.
+fn tee_output(pipe: impl Read + Send + 'static, mut log: File, bot_name: String) {
+    std::thread::spawn(move || {
+        let mut pipe = BufReader::new(pipe);
+        let mut line = vec![];
+        while matches!(pipe.read_until(b'\n', &mut line), Ok(len) if len > 0) {
+            if let Err(e) = log.write_all(&line) {
+                warn!("Could not write the output of '{}': {}", bot_name, e);
+                return;
+            }
+            debug!(
+                "{}: {}",
+                bot_name,
+                String::from_utf8_lossy(&line).trim_end()
+            );
+            line.clear();
+        }
+    });
+}
+
+/// Files of a single game, removed once it's over
+struct GameArtifacts {
+    /// Also names the logs of client bots
+    id: String,
+    dir: PathBuf,
+    keep: bool,
+    /// Written outside of `dir`, listed if the files are kept
+    files: Vec<(String, PathBuf)>,
+}
+
+impl GameArtifacts {
+    fn new(started: SystemTime, keep: bool) -> Self {
+        // Games played in parallel might start at the same time
+        static GAMES: AtomicUsize = AtomicUsize::new(0);
+        let millis = started
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let id = format!(
+            "{}-{:x}-{}",
+            std::process::id(),
+            millis,
+            GAMES.fetch_add(1, Ordering::SeqCst)
+        );
+        Self {
+            dir: std::env::temp_dir().join("bwaishotgun").join(&id),
+            id,
+            keep,
+            files: vec![],
+        }
+    }
+
+    /// Remembers a file of the game, to point to it if the game is kept (ie. for debugging)
+    fn add_file(&mut self, description: String, path: PathBuf) {
+        self.files.push((description, path));
+    }
+
+    /// Folder of the BWAPI.ini of the `index`th bot of the game
+    fn ini_dir(&self, index: usize, bot_name: &str) -> PathBuf {
+        self.dir.join(format!("{}_{}", index, bot_name))
+    }
+
+    /// Folder the `index`th bot of the game is unzipped to, if it's zipped
+    fn unzip_dir(&self, index: usize, bot_name: &str) -> PathBuf {
+        self.dir.join(format!("{}_{}_unzipped", index, bot_name))
+    }
+}
+
+impl Drop for GameArtifacts {
+    fn drop(&mut self) {
+        if self.keep {
+            info!(
+                "Kept the files of the game in '{}'",
+                self.dir.to_string_lossy()
+            );
+            for (description, path) in &self.files {
+                info!("  {}: '{}'", description, path.to_string_lossy());
+            }
+        } else if self.dir.exists() {
+            if let Err(e) = remove_dir_all(&self.dir) {
+                debug!("Could not remove '{}': {}", self.dir.to_string_lossy(), e);
+            }
+        }
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Ctrl-C was pressed, everything started so far should be stopped
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Context of errors of a bot that could not be started, with its name in `bots.toml`
+#[derive(Debug)]
+pub struct BotLaunchFailed(pub String);
+
+impl Display for BotLaunchFailed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not launch '{}'", self.0)
+    }
+}
+
+/// Why `Shotgun::run_game` returned no result
+#[derive(Debug)]
+pub enum LaunchError {
+    /// The game config can't be played (ie. no map)
+    InvalidConfig(anyhow::Error),
+    /// A bot could not be started, the error has a `BotLaunchFailed` context
+    BotLaunchFailed(anyhow::Error),
+    /// Dry runs only write the BWAPI.ini of each bot
+    DryRun,
+    Other(anyhow::Error),
+}
+
+impl Display for LaunchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LaunchError::InvalidConfig(e) => write!(f, "Invalid game config: {:#}", e),
+            LaunchError::BotLaunchFailed(e) | LaunchError::Other(e) => write!(f, "{:#}", e),
+            LaunchError::DryRun => write!(f, "Dry run, no game was played"),
+        }
+    }
+}
+
+impl std::error::Error for LaunchError {}
+
+/// What's needed to collect the result of a bot after the game
+struct PlayedBot {
+    name: String,
+    config_name: String,
+    race: Race,
+    working_dir: PathBuf,
+    replay_dir: PathBuf,
+    launch_retries: u8,
+    bwapi_version: &'static str,
+    /// Logs the frames, so the game is known to have started
+    tournament_module: bool,
+    /// When the bot was launched, 0 is the first
+    launch_order: usize,
+}
+
+#[derive(Debug)]
+pub struct PreparedBot {
+    binary: Binary,
+    bwapi_version: BwapiVersion,
+    debug_binary: Option<PathBuf>,
+    tournament_module: Option<String>,
+    supports_character_name: bool,
+    /// BWAPI 3.7.x only reads `bwapi-data/bwapi.ini`, ignoring `BWAPI_CONFIG_INI`
+    supports_config_ini: bool,
+    race: Race,
+    /// The name it plays with, `config_name` is the one in `bots.toml`
+    name: String,
+    config_name: String,
+    working_dir: PathBuf,
+    log_dir: PathBuf,
+    headful: HeadfulMode,
+    cpu_affinity: Option<Vec<usize>>,
+    priority: Option<ProcessPriority>,
+    observer: bool,
+    jvm_args: Vec<String>,
+    /// The BWAPI.ini the bot came with, to merge into (see `merge_bwapi_ini`)
+    own_bwapi_ini: Option<String>,
+}
+
+impl PreparedBot {
+    fn prepare(
+        config: &BotLaunchConfig,
+        path: &Path,
+        definition: &BotDefinition,
+        bwapi_version: Option<BwapiVersion>,
+        strict: bool,
+        unzip_dir: &Path,
+    ) -> anyhow::Result<Self> {
+        let bwapi_data_path = path.join("bwapi-data");
+        let read_path = bwapi_data_path.join("read");
+        let write_path = bwapi_data_path.join("write");
+        let log_dir = path.join("logs");
+        create_dir_all(read_path).context("Could not create read folder")?;
+        create_dir_all(write_path).context("Could not create write folder")?;
+        create_dir_all(&log_dir).context("Could not create log folder")?;
+        // Zipped bots run where they were unzipped to, along with the files they came with
+        let (working_dir, bot_binary) = match definition.zip(path)? {
+            Some(zip) => Binary::unzip(&zip, unzip_dir)?,
+            None => (path.to_path_buf(), definition.binary(path)?),
+        };
+        let tm_path = working_dir.join("tm");
+        create_dir_all(&tm_path).context("Could not create tm folder")?;
+
+        for entry in tm_path.read_dir()?.flatten().filter(|it| {
+            it.path()
+                .extension()
+                .map(|os| os.to_string_lossy().as_ref() == "csv")
+                .unwrap_or(false)
+        }) {
+            debug!("Removing {}", entry.path().to_string_lossy());
+            remove_file(entry.path()).ok();
+        }
+
+        let debug_executable = definition
+            .debug_executable
+            .as_deref()
+            .map(envvars::expand)
+            .transpose()?;
+        let debug_binary = match debug_executable.as_deref() {
+            Some(s) => match Binary::from_path(path.join(s).as_path()) {
+                Some(Binary::Dll(dll)) => Some(dll),
+                _ => bail!("The debug_executable '{}' must be a DLL", s),
+            },
+            None => None,
+        };
+        let bot_binary = match &debug_binary {
+            Some(dll) if config.debug => Binary::Dll(dll.clone()),
+            None if config.debug => bail!(
+                "'{}' has no debug_executable in its 'bot.toml'",
+                config.name
+            ),
+            _ => bot_binary,
+        };
+        let race = config.race.unwrap_or(definition.race);
+        if !config.jvm_args.is_empty() && !matches!(bot_binary, Binary::Jar(_)) {
+            warn!(
+                "'{}' is no JAR bot, its 'jvm_args' are ignored",
+                config.name
+            );
+        }
+
+        let bwapi_dll = bwapi_data_path.join("BWAPI.dll");
+        let bwapi_version = if let Some(bwapi_version) = bwapi_version {
+            let detected = BwapiVersion::from_dll(&bwapi_dll)
+                .with_context(|| format!("Could not check '{}'", bwapi_dll.to_string_lossy()))?;
+            if detected != Some(bwapi_version) {
+                warn!(
+                    "'{}' is BWAPI {}, treating it as BWAPI {}",
+                    bwapi_dll.to_string_lossy(),
+                    detected
+                        .map(|it| it.version_short().to_string())
+                        .unwrap_or_else(|| format!(
+                            "unknown (CRC 0x{:08X})",
+                            BwapiVersion::dll_crc(&bwapi_dll).unwrap_or_default()
+                        )),
+                    bwapi_version.version_short()
+                );
+            }
+            bwapi_version
+        } else if let Binary::Dll(_) = bot_binary {
+            let crc = BwapiVersion::dll_crc(&bwapi_dll)
+                .with_context(|| format!("Could not check '{}'", bwapi_dll.to_string_lossy()))?;
+            match BwapiVersion::from_u32(crc) {
+                Some(bwapi_version) => bwapi_version,
+                // Fails along with all other unknown BWAPI.dlls of the game
+                None if strict => BwapiVersion::Unknown(crc),
+                // The AI module would only fail to load after StarCraft started
+                None => bail!(
+                    "Unknown BWAPI version of '{}', CRC=0x{:08X} (supported are {})",
+                    bwapi_dll.to_string_lossy(),
+                    crc,
+                    BwapiVersion::KNOWN
+                        .iter()
+                        .map(|it| it.version_short())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        } else {
+            // Clients bring their own BWAPI, a custom server might work
+            BwapiVersion::from_dll_lenient(&bwapi_dll)
+                .with_context(|| format!("Could not check '{}'", bwapi_dll.to_string_lossy()))?
+        };
+        if bwapi_version == BwapiVersion::Bwapi374 {
+            warn!(
+                "'{}' uses BWAPI 3.7.4, BWAPI 3.7.5 is a drop-in replacement with less bugs",
+                config.name
+            );
+        }
+
+        let supports_config_ini = !matches!(
+            bwapi_version,
+            BwapiVersion::Bwapi374 | BwapiVersion::Bwapi375
+        );
+        let own_bwapi_ini = if !definition.merge_bwapi_ini {
+            None
+        } else if !supports_config_ini {
+            warn!(
+                "'{}' uses BWAPI 3.7.x, which only reads the BWAPI.ini BWAIShotgun writes - 'merge_bwapi_ini' is ignored",
+                config.name
+            );
+            None
+        } else {
+            let own = bwapi_data_path.join("bwapi.ini");
+            match std::fs::read_to_string(&own) {
+                Ok(own) => Some(own),
+                Err(e) => {
+                    warn!(
+                        "Could not read '{}' to merge it: {}",
+                        own.to_string_lossy(),
+                        e
+                    );
+                    None
+                }
+            }
+        };
+
+        let tournament_module = match definition.tournament_module.resolve(&bwapi_version)? {
+            Some(tm_source_file) => {
+                let tm_name = tm_source_file
+                    .file_name()
+                    .expect("Tournament module without file name")
+                    .to_string_lossy()
+                    .to_string();
+                std::fs::copy(&tm_source_file, working_dir.join(&tm_name)).with_context(|| {
+                    format!(
+                        "Could not copy tournament module: '{}'",
+                        tm_source_file.to_string_lossy(),
+                    )
+                })?;
+                Some(tm_name)
+            }
+            None => None,
+        };
+
+        let name = config
+            .player_name
+            .clone()
+            .unwrap_or_else(|| config.name.clone());
+        let character_name = sanitize_character_name(&name);
+        if character_name != name {
+            warn!(
+                "'{}' is not a valid character name (max. {} characters, no special characters), using '{}'",
+                name, MAX_CHARACTER_NAME_LEN, character_name
+            );
+        }
+
+        Ok(Self {
+            binary: bot_binary,
+            bwapi_version,
+            debug_binary,
+            race,
+            name: character_name,
+            config_name: config.name.clone(),
+            working_dir,
+            log_dir,
+            headful: config.headful,
+            cpu_affinity: config.cpu_affinity.clone(),
+            priority: config.priority,
+            observer: config.observer,
+            jvm_args: config.jvm_args.clone(),
+            tournament_module,
+            supports_character_name: !matches!(
+                bwapi_version,
+                BwapiVersion::Bwapi374 | BwapiVersion::Bwapi375 | BwapiVersion::Bwapi412
+            ),
+            supports_config_ini,
+            own_bwapi_ini,
+        })
+    }
+}
+
+impl PreparedBot {
+    /// How BWAPI will run the bot, with its BWAPI.ini in `ini_dir`
+    fn bot_setup(
+        &self,
+        game_config: &GameConfig,
+        starcraft_exe: &Path,
+        starcraft_path: &Path,
+        sandbox: &SandboxMode,
+        ini_dir: PathBuf,
+        replay_root: Option<PathBuf>,
+    ) -> BotSetup {
+        BotSetup {
+            starcraft_exe: starcraft_exe.to_path_buf(),
+            starcraft_path: starcraft_path.to_path_buf(),
+            bot_base_path: self.working_dir.clone(),
+            ini_dir,
+            tournament_module: self.tournament_module.as_ref().map(PathBuf::from),
+            replay_template: match game_config.replay_template.as_deref() {
+                None => Some(DEFAULT_REPLAY_TEMPLATE.to_string()),
+                Some("") => None,
+                Some(template) => Some(template.to_string()),
+            },
+            replay_root,
+            player_name: self.name.clone(),
+            race: self.race,
+            observer: self.observer,
+            game_speed: match game_config.game_speed {
+                Some(game_speed) => game_speed.to_override(),
+                None if game_config.human_speed => GameSpeed::Human.to_override(),
+                None => 0,
+            },
+            sound: plays_sound(game_config, self.headful),
+            auto_restart: game_config.auto_restart,
+            sandbox: sandbox.clone(),
+            bot_binary: self.binary.clone(),
+            debug_binary: self.debug_binary.clone(),
+            own_bwapi_ini: self.own_bwapi_ini.clone(),
+        }
+    }
+
+    /// Headful + Host => All other bots need to join the game with this bots player name
+    fn headful_host_name(&self, game_config: &GameConfig, bot_setup: &BotSetup) -> Option<String> {
+        if game_config.starcraft_flavor == StarcraftFlavor::OpenBW
+            || matches!(self.headful, HeadfulMode::Off)
+        {
+            None
+        } else if self.supports_character_name {
+            Some(bot_setup.character_name())
+        } else {
+            warn!("Headful hosting bot uses very old BWAPI version, please ensure there's only one character with the name 'BWAPI'.");
+            Some("BWAPI".to_string())
+        }
+    }
+
+    /// The BWAPI version for logs and results - clients link their own BWAPI, which shotgun can't tell
+    fn bwapi_version_label(&self) -> &'static str {
+        match self.binary {
+            Binary::Dll(_) => self.bwapi_version.version_short(),
+            _ => "bundled/unknown",
+        }
+    }
+}
+
+/// Reads the `bot.toml` of the bot, returns the bot folder with it
+fn load_bot(cfg: &BotLaunchConfig) -> anyhow::Result<(PathBuf, BotDefinition)> {
+    let mut bot_folder = base_folder();
+    bot_folder.push("bots");
+    bot_folder.push(&cfg.name);
+    let bot_definition = toml::from_slice::<BotDefinition>(
+        read(bot_folder.join("bot.toml"))
+            .with_context(|| {
+                format!(
+                    "Could not read 'bot.toml' for bot '{}' in: '{}'",
+                    cfg.name,
+                    bot_folder.to_string_lossy(),
+                )
+            })?
+            .as_slice(),
+    )
+    .with_context(|| BotLaunchFailed(cfg.name.clone()))?;
+    if let Some(race) = &cfg.race {
+        if bot_definition.race != Race::Random && &bot_definition.race != race {
+            info!(
+                "Bot '{}' is configured to play as {}, but its default race is {}!",
+                cfg.name, race, bot_definition.race
+            );
+        }
+    }
+    Ok((bot_folder, bot_definition))
+}
+
+/// Writes the BWAPI.ini the only bot of `game_config` would host its game with
+fn emit_bwapi_ini(
+    shotgun: &Shotgun,
+    game_config: &GameConfig,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let GameType::Melee(bots) = &game_config.game_type;
+    let config = match bots.as_slice() {
+        [config] => config,
+        _ => bail!("Can only write the BWAPI.ini of one bot"),
+    };
+    let (path, definition) = load_bot(config)?;
+    // The folder it would be unzipped to is gone right after
+    ensure!(
+        definition.zip(&path)?.is_none(),
+        "'{}' is a zipped bot, it's unzipped to a new temporary folder for each game - there's no BWAPI.ini to write for it",
+        config.name
+    );
+    let artifacts = GameArtifacts::new(SystemTime::now(), false);
+    let bot = PreparedBot::prepare(
+        config,
+        &path,
+        &definition,
+        game_config.bwapi_version,
+        game_config.strict,
+        &artifacts.unzip_dir(0, &config.name),
+    )?;
+    let starcraft_path = &shotgun.starcraft_path;
+    let starcraft_exe = game_config.starcraft_flavor.executable(starcraft_path);
+    let bot_setup = bot.bot_setup(
+        game_config,
+        &starcraft_exe,
+        starcraft_path,
+        &shotgun.sandbox,
+        bot.working_dir.join("bwapi-data"),
+        game_config
+            .replay_root
+            .as_ref()
+            .map(|replay_root| replay_root.join(&bot.config_name)),
+    );
+    // Not a generated one, the output should only depend on the input
+    let game_name = bot
+        .headful_host_name(game_config, &bot_setup)
+        .or_else(|| game_config.game_name.clone())
+        .unwrap_or_else(|| "shotgun".to_string());
+    let launcher = bwapi_launcher(
+        game_config,
+        bot_setup,
+        bot.headful,
+        !game_config.human_host,
+        &game_name,
+        1,
+    )?;
+    launcher.bwapi_ini().write(out)?;
+    Ok(())
+}
+
+/// Runs whatever the command line asks for
+pub fn run() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    if cli.list_versions {
+        BwapiVersion::write_known(&mut std::io::stdout())?;
+        return Ok(());
+    }
+    let emit_ini = cli.emit_ini();
+    let watch = cli.watch();
+    TermLogger::init(
+        cli.log_level(),
+        Config::default(),
+        // Keep stdout to the BWAPI.ini or game table
+        if emit_ini || watch.is_some() {
+            TerminalMode::Stderr
+        } else {
+            TerminalMode::Mixed
+        },
+        ColorChoice::Auto,
+    )?;
+    info!(
+        "Welcome to {} {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    let check = cli.check();
+    let mut shotgun = if check || watch.is_some() {
+        Shotgun::with_any_starcraft_dir(cli.starcraft_dir.as_deref())?
+    } else {
+        Shotgun::with_starcraft_dir(cli.starcraft_dir.as_deref())?
+    };
+
+    if !check
+        && !emit_ini
+        && watch.is_none()
+        && matches!(
+            shotgun.sandbox,
+            SandboxMode::Unconfigured | SandboxMode::NoSandbox
+        )
+    {
+        // Currently, we don't support bot sandboxing
+        debug!("You're running bots without a sandbox.");
+        if let SandboxMode::Unconfigured = shotgun.sandbox {
+            warn!("If you are sure you don't want use a sandbox, please edit 'shotgun.toml' and set the sandbox to 'NoSandbox'.");
+            warn!("Will wait for 15 seconds (press ctrl+c to abort now, or wait and start the bots anyways).");
+            std::thread::sleep(Duration::from_secs(15));
+        }
+    }
+
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // Pressed twice, don't wait any longer
+            std::process::exit(130);
+        }
+        warn!("Stopping all bots (press ctrl+c again to exit immediately)");
+    })?;
+    if let Some(interval) = watch {
+        return watch::run(
+            &mut shotgun.game_table_access,
+            interval,
+            &mut std::io::stdout(),
+        );
+    }
+
+    let tournament = cli.tournament();
+    let series = cli.series();
+    let bench = cli.bench();
+
+    // The checklist looks for the map itself, StarCraft might not be there
+    if !check {
+        if let Err(e) = cli.check_maps(&shotgun.starcraft_path) {
+            e.exit();
+        }
+    }
+    let game_config = match &cli.config {
+        Some(config) => Some(GameConfig::load(config)?),
+        // Without a game on the command line, the default config is used
+        None if !cli.has_game() => Some(GameConfig::load(&base_folder().join("game.toml"))?),
+        None => None,
+    };
+    let game_config = match game_config {
+        Some(mut game_config) => cli.apply(&mut game_config).map(|_| game_config),
+        None => cli.try_into(),
+    };
+    let game_config = match game_config {
+        Ok(game_config) => game_config,
+        Err(e) => e.exit(),
+    };
+    debug!("Game config:\n{}", game_config);
+    trace!("{:#?}", game_config);
+    if check {
+        return check::run(&mut shotgun, &game_config);
+    }
+    let mut game_config = game_config;
+    game_config.prepare(&shotgun.starcraft_path)?;
+    if emit_ini {
+        game_config.resolve_map_pool(&shotgun.starcraft_path, &mut seeded_rng(game_config.seed))?;
+        return emit_bwapi_ini(&shotgun, &game_config, &mut std::io::stdout());
+    }
+    let starcraft_path = &shotgun.starcraft_path;
+
+    if game_config.starcraft_flavor == StarcraftFlavor::OpenBW {
+        // OpenBW brings its own networking
+    } else if let Ok(metadata) = metadata(starcraft_path.join("SNP_DirectIP.snp")) {
+        if metadata.len() != 46100 {
+            warn!("The 'SNP_DirectIP.snp' in your StarCraft installation might not support more than ~6 bots per game. Overwrite with the included 'SNP_DirectIP.snp' file to support more.");
+        }
+    } else {
+        warn!("Could not find 'SNP_DirectIP.snp' in your StarCraft installation, please copy the provided one or install BWAPI.");
+    }
+
+    // Dry runs don't start StarCraft, no need to look at the game table
+    let stale_game_table = if game_config.dry_run || !game_config.starcraft_flavor.uses_game_table()
+    {
+        None
+    } else {
+        shotgun
+            .game_table_access
+            .get_game_table()
+            .context("Could not open the game table of BWAPI")?
+    };
+    if let Some(game_table) = stale_game_table {
+        warn!(
+            "Detected a stale game table. If you did not run Starcraft with BWAPI yourself, \
+        you should kill all running instances of StarCraft and any lingering bots."
+        );
+
+        for server_process_id in game_table
+            .game_instances
+            .iter()
+            .filter(|it| it.is_connected && it.server_process_id != 0)
+            .map(|it| it.server_process_id)
+        {
+            warn!(
+            "The process {} is in the game table already and will interfere with game creation.",
+            server_process_id
+        );
+        }
+    }
+
+    if let Some(tournament) = tournament {
+        return tournament.run(
+            &mut shotgun,
+            &game_config,
+            &mut seeded_rng(game_config.seed),
+        );
+    }
+    if let Some(bench) = bench {
+        return bench.run(
+            &mut shotgun,
+            &game_config,
+            &mut seeded_rng(game_config.seed),
+        );
+    }
+    if let Some(series) = series {
+        return series.run(
+            &mut shotgun,
+            &game_config,
+            &mut seeded_rng(game_config.seed),
+        );
+    }
+
+    let GameType::Melee(bots) = &game_config.game_type;
+    match shotgun.run_game(&game_config, bots) {
+        Ok(result) => {
+            let result_file = base_folder().join("result.json");
+            result
+                .write_result(&mut File::create(&result_file)?)
+                .with_context(|| format!("Could not write '{}'", result_file.to_string_lossy()))?;
+        }
+        Err(LaunchError::DryRun) => {}
+        Err(
+            LaunchError::InvalidConfig(e) | LaunchError::BotLaunchFailed(e) | LaunchError::Other(e),
+        ) => return Err(e),
+    }
+    info!("Done");
+    Ok(())
+}
+
+/// Logged, so a game can be reproduced with `--seed`
+fn seeded_rng(seed: Option<u64>) -> StdRng {
+    let seed = seed.unwrap_or_else(|| StdRng::from_entropy().gen());
+    info!("Using seed {}", seed);
+    StdRng::seed_from_u64(seed)
+}
+
+/// `sound` of the game config (ie. `--sound`) wins over `no_sound` of the bot, without a window
+/// there's nothing to hear
+fn plays_sound(game_config: &GameConfig, headful: HeadfulMode) -> bool {
+    game_config.sound.unwrap_or(matches!(
+        headful,
+        HeadfulMode::On { no_sound, .. } | HeadfulMode::Minimized { no_sound } if !no_sound
+    ))
+}
+
+/// How to start a bot: With OpenBW, with a StarCraft window (injectory) or without one (bwheadless).
+/// Only `host` creates the game, all others join `game_name`
+fn bwapi_launcher(
+    game_config: &GameConfig,
+    bot_setup: BotSetup,
+    headful: HeadfulMode,
+    host: bool,
+    game_name: &str,
+    player_count: usize,
+) -> anyhow::Result<Box<dyn LaunchBuilder>> {
+    // The human would end up in a different game than the bot
+    ensure!(
+        !(host && game_config.human_host),
+        "'{}' can't host a game a human should host",
+        bot_setup.player_name
+    );
+    // Without a game name, bots join whatever the human hosts
+    let join_first = game_config.human_host && game_config.game_name.is_none();
+    let lan_mode = game_config.lan_mode.unwrap_or(BwapiLanMode::LocalPC);
+    // There's no menu to pick single player games in with bwheadless or OpenBW
+    ensure!(
+        !game_config.vs_computer
+            || game_config.starcraft_flavor == StarcraftFlavor::Retail
+                && !matches!(headful, HeadfulMode::Off),
+        "'{}' needs a StarCraft window to play against the computer (see '--headful')",
+        bot_setup.player_name
+    );
+    // Observers are welcome, but not needed to start the game - unless all bots should be ready
+    let GameType::Melee(bots) = &game_config.game_type;
+    let observers = if game_config.wait.wait_all_ready {
+        0
+    } else {
+        bots.iter().filter(|bot| bot.observer).count()
+    };
+    let min_players = game_config
+        .min_players
+        .unwrap_or_else(|| player_count.saturating_sub(observers).max(1));
+    let max_players = game_config.max_players.unwrap_or(player_count);
+    // Tournaments only know the number of players per game
+    ensure!(
+        !host || min_players <= max_players && player_count <= max_players,
+        "Can't host a game for {} to {} players with {} bots",
+        min_players,
+        max_players,
+        player_count
+    );
+    Ok(if game_config.starcraft_flavor == StarcraftFlavor::OpenBW {
+        Box::new(OpenBw {
+            bot_setup,
+            game_name: if join_first {
+                "JOIN_FIRST".to_string()
+            } else {
+                game_name.to_string()
+            },
+            connect_mode: if host {
+                OpenBwConnectMode::Host {
+                    map: game_config
+                        .map
+                        .clone()
+                        .ok_or_else(|| anyhow!("OpenBW cannot host without a map"))?,
+                    min_players,
+                    max_players,
+                }
+            } else {
+                OpenBwConnectMode::Join
+            },
+            headful: !matches!(headful, HeadfulMode::Off),
+        })
+    } else if !matches!(headful, HeadfulMode::Off) {
+        Box::new(Injectory {
+            bot_setup,
+            game_name: if join_first {
+                "JOIN_FIRST".to_string()
+            } else {
+                game_name.to_string()
+            },
+            connect_mode: if host && game_config.vs_computer {
+                InjectoryConnectMode::SinglePlayer {
+                    map: game_config
+                        .map
+                        .clone()
+                        .ok_or_else(|| anyhow!("Can't play against the computer without a map"))?,
+                    computer_races: game_config.computer_races.clone(),
+                }
+            } else if host {
+                InjectoryConnectMode::Host {
+                    map: game_config.map.clone(),
+                    min_players,
+                    max_players,
+                }
+            } else {
+                InjectoryConnectMode::Join
+            },
+            lan_mode,
+            wmode: matches!(headful, HeadfulMode::On { no_wmode, resolution: None, .. } if !no_wmode)
+                || matches!(headful, HeadfulMode::Minimized { .. }),
+            resolution: match headful {
+                HeadfulMode::On { resolution, .. } => resolution,
+                _ => None,
+            },
+        })
+    } else {
+        // Without a 'min_players', it's only lower for observers
+        ensure!(
+            !host || min_players == max_players || game_config.min_players.is_none(),
+            "'{}' can't host a game for {} to {} players without a StarCraft window, bwheadless waits for all of them",
+            bot_setup.player_name,
+            min_players,
+            max_players
+        );
+        Box::new(BwHeadless {
+            bot_setup,
+            game_name: if join_first {
+                None
+            } else {
+                Some(game_name.to_string())
+            },
+            connect_mode: if host {
+                BwHeadlessConnectMode::Host {
+                    map: game_config
+                        .map
+                        .clone()
+                        .ok_or_else(|| anyhow!("bwheadless cannot host without a map"))?,
+                    player_count: max_players,
+                }
+            } else {
+                BwHeadlessConnectMode::Join
+            },
+            lan_mode,
+        })
+    })
+}
+
+/// Sorts `bots` into launch order, which is also their order in the lobby - optionally shuffled first.
+/// Client bots *must* be ran first, as they need to connect to their resp. BWAPI Server: Each client
+/// connects to the first server waiting in the game table, if a DLL bot's server was started
+/// before, the client would take its slot.
+fn order_slots<T>(bots: &mut [T], is_dll: impl Fn(&T) -> bool, shuffle: bool, rng: &mut impl Rng) {
+    if shuffle {
+        bots.shuffle(rng);
+    }
+    bots.sort_by_key(is_dll);
+}
+
+/// BWAPI only works with the StarCraft it was built for, anything else fails after StarCraft started.
+/// Only a retail `starcraft_exe` is checked, OpenBW has none. With `strict`, unknown BWAPI.dlls
+/// fail as well (with any flavor), listing all offending bots at once.
+fn check_starcraft_version(
+    starcraft_exe: Option<&Path>,
+    bots: &[PreparedBot],
+    strict: bool,
+) -> anyhow::Result<()> {
+    let exe = starcraft_exe
+        .map(|starcraft_exe| {
+            read(starcraft_exe)
+                .with_context(|| format!("Could not read '{}'", starcraft_exe.to_string_lossy()))
+                .map(|exe| (starcraft_exe, exe))
+        })
+        .transpose()?;
+    let bots: Vec<_> = bots
+        .iter()
+        .map(|bot| (bot.name.as_str(), bot.bwapi_version))
+        .collect();
+    let problems = version_problems(
+        exe.as_ref().map(|(path, exe)| (*path, exe.as_slice())),
+        &bots,
+        strict,
+    );
+    ensure!(
+        !strict || problems.is_empty(),
+        "Not starting the game in strict mode, {} bots would run an unvetted BWAPI or StarCraft:\n{}",
+        problems.len(),
+        problems.join("\n")
+    );
+    for problem in problems {
+        warn!("{}", problem);
+    }
+    Ok(())
+}
+
+/// What's wrong with the BWAPI `bots` (name and version) would run with StarCraft (path and
+/// `exe`, if there is one to check): All problems with `strict`, otherwise only the first
+/// mismatch - all bots share the same StarCraft, and unknown BWAPI.dlls were already logged
+fn version_problems(
+    starcraft: Option<(&Path, &[u8])>,
+    bots: &[(&str, BwapiVersion)],
+    strict: bool,
+) -> Vec<String> {
+    let mut problems = vec![];
+    for &(name, bwapi_version) in bots {
+        let (expected, starcraft_exe, exe) =
+            match (bwapi_version.starcraft_version(), bwapi_version, starcraft) {
+                (None, BwapiVersion::Unknown(crc), _) if strict => {
+                    problems.push(format!(
+                        "'{}' has an unknown BWAPI.dll (CRC 0x{:08X})",
+                        name, crc
+                    ));
+                    continue;
+                }
+                (Some(expected), _, Some((starcraft_exe, exe))) => (expected, starcraft_exe, exe),
+                _ => continue,
+            };
+        let version = bwapi::file_version(exe);
+        if matches!(version, Some(version) if version[..3] == expected) {
+            continue;
+        }
+        problems.push(format!(
+            "'{}' (CRC 0x{:08X}) is StarCraft {}, but BWAPI {} of '{}' needs StarCraft {}",
+            starcraft_exe.to_string_lossy(),
+            BwapiVersion::crc(exe),
+            version
+                .map(|version| format_version(&version))
+                .unwrap_or_else(|| "of an unknown version".to_string()),
+            bwapi_version.version_short(),
+            name,
+            format_version(&expected)
+        ));
+        if !strict {
+            break;
+        }
+    }
+    problems
+}
+
+fn format_version(version: &[u16]) -> String {
+    version
+        .iter()
+        .map(|it| it.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Pins a process of the bot `name` to `cores` and sets its priority, failing that is not fatal
+fn apply_process_settings(
+    name: &str,
+    cores: Option<&[usize]>,
+    priority: Option<ProcessPriority>,
+    process_id: u32,
+) {
+    if let Some(cores) = cores {
+        if let Err(e) = process::set_affinity(process_id, cores) {
+            warn!(
+                "Could not set the CPU affinity of process {} of '{}': {}",
+                process_id, name, e
+            );
+        }
+    }
+    if let Some(priority) = priority {
+        if let Err(e) = process::set_priority(process_id, priority) {
+            warn!(
+                "Could not set the priority of process {} of '{}': {}",
+                process_id, name, e
+            );
+        }
+    }
+}
+
+/// Which of `bot_count` bots host, in launch order: The first one, unless a human hosts. A game
+/// needs exactly one host - slots fill up just the same without, but the game would never start.
+/// That's one host by construction, only a game without bots and without a human has none.
+fn plan_hosts(bot_count: usize, human_host: bool) -> anyhow::Result<Vec<bool>> {
+    ensure!(
+        bot_count > 0 || human_host,
+        "No bot is hosting the game, and no human either"
+    );
+    Ok((0..bot_count)
+        .map(|index| index == 0 && !human_host)
+        .collect())
+}
+
+/// Everything needed to play games, besides the games themselves
+pub struct Shotgun {
+    pub starcraft_path: PathBuf,
+    java_path: Option<String>,
+    sandbox: SandboxMode,
+    game_table_access: GameTableAccess,
+    /// Held while bots are launched: Clients connect to the first waiting server, even of another game
+    launch_lock: Arc<Mutex<()>>,
+}
+
+impl Shotgun {
+    /// Reads `shotgun.toml` (or uses the defaults if there is none)
+    pub fn new() -> anyhow::Result<Self> {
+        Self::with_starcraft_dir(None)
+    }
+
+    /// Like `new`, but uses the StarCraft in `starcraft_dir` if it's `Some`
+    pub fn with_starcraft_dir(starcraft_dir: Option<&Path>) -> anyhow::Result<Self> {
+        Self::load(starcraft_dir, true)
+    }
+
+    /// Like `with_starcraft_dir`, but keeps a StarCraft folder that's invalid: `check` tells
+    /// what's wrong with it, `watch` doesn't need one
+    pub fn with_any_starcraft_dir(starcraft_dir: Option<&Path>) -> anyhow::Result<Self> {
+        Self::load(starcraft_dir, false)
+    }
+
+    fn load(starcraft_dir: Option<&Path>, validate: bool) -> anyhow::Result<Self> {
+        let ShotgunConfig {
+            starcraft_path,
+            java_path,
+            sandbox,
+            game_table_os_id,
+        } = if let Ok(cfg) = read(base_folder().join("shotgun.toml")) {
+            toml::from_slice(cfg.as_slice()).context("'shotgun.toml' is invalid")?
+        } else {
+            warn!("'shotgun.toml' not found, using defaults");
+            ShotgunConfig::default()
+        };
+        Ok(Shotgun {
+            starcraft_path: if validate {
+                starcraft_path.ensure_path(starcraft_dir)?
+            } else {
+                starcraft_path.find(starcraft_dir)?.0
+            },
+            java_path,
+            sandbox,
+            game_table_access: match game_table_os_id {
+                Some(os_id) => GameTableAccess::with_os_id(os_id),
+                None => GameTableAccess::new(),
+            },
+            launch_lock: Arc::default(),
+        })
+    }
+
+    /// Plays one game of `bots` with the settings of `config` (its bots are ignored), as the
+    /// command line does. Can be called for game after game, the game table is kept open.
+    pub fn run_game(
+        &mut self,
+        config: &GameConfig,
+        bots: &[BotLaunchConfig],
+    ) -> Result<GameResult, LaunchError> {
+        let mut game_config = config.clone();
+        game_config.game_type = GameType::Melee(bots.to_vec());
+        game_config
+            .prepare(&self.starcraft_path)
+            .map_err(LaunchError::InvalidConfig)?;
+        let mut rng = seeded_rng(game_config.seed);
+        game_config
+            .resolve_map_pool(&self.starcraft_path, &mut rng)
+            .map_err(LaunchError::InvalidConfig)?;
+        game_config.resolve_game_name(&mut rng);
+        match self.play(&game_config, &mut rng) {
+            Ok(Some(result)) => Ok(result),
+            Ok(None) => Err(LaunchError::DryRun),
+            Err(e) if e.downcast_ref::<BotLaunchFailed>().is_some() => {
+                Err(LaunchError::BotLaunchFailed(e))
+            }
+            Err(e) => Err(LaunchError::Other(e)),
+        }
+    }
+
+    /// Creates a `Shotgun` for another thread, playing games alongside this one
+    pub fn worker(&self) -> impl FnOnce() -> Shotgun + Send {
+        let starcraft_path = self.starcraft_path.clone();
+        let java_path = self.java_path.clone();
+        let sandbox = self.sandbox.clone();
+        let os_id = self.game_table_access.os_id().to_string();
+        let launch_lock = self.launch_lock.clone();
+        move || Shotgun {
+            starcraft_path,
+            java_path,
+            sandbox,
+            game_table_access: GameTableAccess::with_os_id(os_id),
+            launch_lock,
+        }
+    }
+
+    /// Slots of the game table available for bots, and of those the ones not taken
+    pub fn slots(&mut self) -> anyhow::Result<(usize, usize)> {
+        self.game_table_access.clear_stale_slots();
+        Ok((
+            self.game_table_access.slot_count()?,
+            self.game_table_access.free_slot_count()?,
+        ))
+    }
+
+    /// Lets go of BWAPI's game table until the next game, so Windows can free it once the last
+    /// StarCraft is gone
+    pub fn close_game_table(&mut self) {
+        self.game_table_access.close();
+    }
+
+    /// Waits `delay` after a game, and then up to `max_wait` until BWAPI released enough slots
+    /// for `free` bots - slots of the last game might still be taken for a moment
+    pub fn cool_down(
+        &mut self,
+        game_config: &GameConfig,
+        delay: Duration,
+        free: usize,
+    ) -> anyhow::Result<()> {
+        if game_config.dry_run || interrupted() {
+            return Ok(());
+        }
+        std::thread::sleep(delay);
+        if !game_config.starcraft_flavor.uses_game_table() {
+            return Ok(());
+        }
+        let deadline = Instant::now() + game_config.wait.max_wait;
+        while self.slots()?.1 < free {
+            if interrupted() {
+                break;
+            }
+            if Instant::now() >= deadline {
+                warn!("The slots of the last game were not released in time, starting the next game anyway");
+                break;
+            }
+            std::thread::sleep(game_config.wait.poll_interval);
+        }
+        Ok(())
+    }
+
+    /// Plays a game until all bots are done, `None` on dry runs. Errors of bots failing to
+    /// start have a `BotLaunchFailed` context.
+    pub fn play(
+        &mut self,
+        game_config: &GameConfig,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<Option<GameResult>> {
+        let launch_lock = self.launch_lock.clone();
+        let launching = launch_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let launch_started = Instant::now();
+        let Shotgun {
+            starcraft_path,
+            java_path,
+            sandbox,
+            game_table_access,
+            ..
+        } = self;
+        let starcraft_exe = game_config.starcraft_flavor.executable(starcraft_path);
+        let uses_game_table = game_config.starcraft_flavor.uses_game_table();
+        match game_config.game_type {
+            GameType::Melee(ref bots) => {
+                let bots: anyhow::Result<Vec<_>> = bots
+                    .iter()
+                    .map(|cfg| {
+                        let (bot_folder, bot_definition) = load_bot(cfg)?;
+                        Ok((cfg, bot_folder, bot_definition))
+                    })
+                    .collect();
+                let bots = bots?;
+                let player_count = bots.len();
+                let started = SystemTime::now();
+                // Declared before the bots, so it's dropped after they are killed
+                let mut artifacts =
+                    GameArtifacts::new(started, game_config.keep_artifacts || game_config.dry_run);
+                let prepared_bots: anyhow::Result<Vec<_>> = bots
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (config, path, definition))| {
+                        PreparedBot::prepare(
+                            config,
+                            path,
+                            definition,
+                            game_config.bwapi_version,
+                            game_config.strict,
+                            &artifacts.unzip_dir(i, &config.name),
+                        )
+                        .with_context(|| BotLaunchFailed(config.name.clone()))
+                    })
+                    .collect();
+                let mut prepared_bots = prepared_bots?;
+                check_starcraft_version(
+                    (game_config.starcraft_flavor == StarcraftFlavor::Retail)
+                        .then_some(starcraft_exe.as_path()),
+                    &prepared_bots,
+                    game_config.strict,
+                )?;
+
+                if game_config.roll_random_race {
+                    for bot in prepared_bots
+                        .iter_mut()
+                        .filter(|bot| bot.race == Race::Random && !bot.observer)
+                    {
+                        bot.race = bot.race.resolve(rng);
+                        info!("Rolled {} for '{}'", bot.race, bot.name);
+                    }
+                }
+
+                order_slots(
+                    &mut prepared_bots,
+                    |bot| matches!(bot.binary, Binary::Dll(_)),
+                    game_config.shuffle_slots,
+                    rng,
+                );
+                if game_config.shuffle_slots {
+                    info!(
+                        "Launch order: {}",
+                        prepared_bots
+                            .iter()
+                            .map(|bot| format!("'{}'", bot.name))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+
+                let mut bot_names = HashSet::new();
+                for bot in prepared_bots.iter().map(|it| &it.name) {
+                    if !bot_names.insert(bot) {
+                        warn!("'{}' was added multiple times. All instances will use the same read/write/log folders and could fail to work properly. Also headful mode will not work as expected.", bot);
+                    }
+                }
+                let mut instances = vec![];
+                let mut played_bots = vec![];
+                let deadline = game_config
+                    .time_out_at_seconds
+                    .map(|secs| Instant::now() + Duration::from_secs(secs));
+                // Humans take their time to start the game they host
+                let lobby_timeout = game_config.wait.lobby_timeout;
+                let lobby_deadline = (!lobby_timeout.is_zero() && !game_config.human_host)
+                    .then(|| Instant::now() + lobby_timeout);
+                // If a human is going to host, no need to fire up a host
+                let hosts = plan_hosts(prepared_bots.len(), game_config.human_host)?;
+                // Servers of the StarCraft instances of this game, other games don't count
+                let mut game_pids = vec![];
+                // Game name is mutable, BWAPI can't create games with names differing from the player name in LAN
+                let mut game_name = game_config
+                    .game_name
+                    .as_deref()
+                    .unwrap_or("shotgun")
+                    .to_string();
+                for (index, bot) in prepared_bots.into_iter().enumerate() {
+                    ensure!(!interrupted(), "Interrupted while starting bots");
+                    let host = hosts[index];
+                    if matches!(lobby_deadline, Some(deadline) if Instant::now() >= deadline) {
+                        bail!(
+                            "The game never started: Gave up after {} seconds while launching '{}' ({} of {} bots running)",
+                            lobby_timeout.as_secs(),
+                            bot.name,
+                            index,
+                            player_count
+                        );
+                    }
+                    // One folder per bot (as in `bots.toml`, not the name it plays with), as with replays in the bot folders
+                    let replay_root = game_config
+                        .replay_root
+                        .as_ref()
+                        .map(|replay_root| replay_root.join(&bot.config_name));
+                    if let Some(replay_root) = &replay_root {
+                        // BWAPI doesn't save replays if the folder is missing
+                        create_dir_all(replay_root).with_context(|| {
+                            format!("Could not create '{}'", replay_root.to_string_lossy())
+                        })?;
+                    }
+                    let replay_dir = match &replay_root {
+                        Some(replay_root) => replay_root.clone(),
+                        None => bot.working_dir.join("replays"),
+                    };
+                    // Per game, so concurrent games don't overwrite each others settings
+                    let ini_dir = if bot.supports_config_ini {
+                        artifacts.ini_dir(index, &bot.name)
+                    } else {
+                        bot.working_dir.join("bwapi-data")
+                    };
+                    let bot_setup = bot.bot_setup(
+                        game_config,
+                        &starcraft_exe,
+                        starcraft_path,
+                        sandbox,
+                        ini_dir,
+                        replay_root,
+                    );
+                    let tournament_module = bot_setup.tournament_module.clone();
+                    let bwapi_ini_path = bot_setup.bwapi_ini_path();
+                    if host {
+                        if let Some(host_name) = bot.headful_host_name(game_config, &bot_setup) {
+                            game_name = host_name;
+                            info!(
+                                "Headful host '{}' renames the game to '{}'",
+                                bot.name, game_name
+                            );
+                        }
+                    }
+                    let bwapi_launcher = bwapi_launcher(
+                        game_config,
+                        bot_setup,
+                        bot.headful,
+                        host,
+                        &game_name,
+                        player_count,
+                    )?;
+                    info!(
+                        "{} game with '{}'{}",
+                        if host { "Hosting" } else { "Joining" },
+                        bot.name,
+                        tournament_module
+                            .map(|tm| format!(
+                                " (with tournament module '{}')",
+                                tm.to_string_lossy()
+                            ))
+                            .unwrap_or_else(|| "".to_string())
+                    );
+                    let connect_mode = if host {
+                        format!(
+                            "Host (map: {}, {} players)",
+                            game_config.map.as_deref().unwrap_or("-"),
+                            player_count
+                        )
+                    } else {
+                        "Join".to_string()
+                    };
+
+                    debug!(
+                        "BWAPI.ini of '{}': {:#?}",
+                        bot.name,
+                        bwapi_launcher.bwapi_ini()
+                    );
+                    let mut cmd = bwapi_launcher
+                        .build_command(game_config)
+                        .with_context(|| BotLaunchFailed(bot.config_name.clone()))?;
+                    if game_config.dry_run {
+                        println!("Bot '{}'", bot.name);
+                        println!(
+                            "  BWAPI.ini written to: {}",
+                            bwapi_ini_path.to_string_lossy()
+                        );
+                        println!("  Binary: {:?}", bot.binary);
+                        println!("  Connect mode: {}", connect_mode);
+                        println!("  Auto menu: {:?}", bwapi_launcher.bwapi_ini().auto_menu);
+                        continue;
+                    }
+                    cmd.stdout(File::create(bot.log_dir.join("game_out.log"))?)
+                        .stderr(File::create(bot.log_dir.join("game_err.log"))?);
+                    artifacts.add_file(format!("BWAPI.ini of '{}'", bot.name), bwapi_ini_path);
+                    let bot_log = bot.log_dir.join(format!("{}.log", artifacts.id));
+                    for log in ["game_out.log", "game_err.log"] {
+                        artifacts
+                            .add_file(format!("{} of '{}'", log, bot.name), bot.log_dir.join(log));
+                    }
+                    if !matches!(bot.binary, Binary::Dll(_)) {
+                        artifacts.add_file(format!("Output of '{}'", bot.name), bot_log.clone());
+                    }
+                    artifacts.add_file(format!("Replays of '{}'", bot.name), replay_dir.clone());
+                    // Absolute, OpenBW does not run in the bot folder
+                    let tm_dir = bot.working_dir.join("tm");
+                    let cmd = cmd
+                        .env("TM_LOG_FRAMETIMES", tm_dir.join("frames.csv"))
+                        .env("TM_LOG_RESULTS", tm_dir.join("result.csv"))
+                        .env("TM_LOG_UNIT_EVENTS", tm_dir.join("unit_events.csv"));
+                    if let Some(time_out_at_frame) = game_config.time_out_at_frame {
+                        cmd.env("TM_TIME_OUT_AT_FRAME", time_out_at_frame.to_string());
+                    }
+                    let bwapi_version = bot.bwapi_version_label();
+                    info!("Launching '{}' with BWAPI {}", bot.name, bwapi_version);
+                    // To tell which StarCraft belongs to this bot
+                    let occupied_pids = if uses_game_table {
+                        game_table_access.clear_stale_slots();
+                        game_table_access
+                            .occupied_pids()
+                            .context("Could not read the game table")?
+                    } else {
+                        vec![]
+                    };
+                    // Kills bwheadless (and the bot) if the bot fails to launch
+                    let mut instance = BotProcess {
+                        name: bot.name.clone(),
+                        bwheadless: cmd
+                            .spawn()
+                            .context(
+                                "Could not run bwheadless (maybe deleted/blocked by a Virus Scanner?)",
+                            )
+                            .with_context(|| BotLaunchFailed(bot.config_name.clone()))?,
+                        bot: None,
+                        headful: !matches!(bot.headful, HeadfulMode::Off),
+                        observer: bot.observer,
+                    };
+                    // The first BWAPI creates the game table (unless another game did), which takes a
+                    // while on a cold machine. It's there for all bots after.
+                    if uses_game_table && index == 0 {
+                        game_table_access
+                            .wait_for_game_table(&game_config.wait, || instance.gave_up())
+                            .with_context(|| BotLaunchFailed(bot.config_name.clone()))?;
+                    }
+                    // BWAPI takes a slot once it's running, if it doesn't load the AI module can't either.
+                    // Unlike clients which are slow to connect, relaunching won't help here.
+                    if uses_game_table && matches!(bot.binary, Binary::Dll(_)) {
+                        let injected = game_table_access.poll(
+                            game_config.wait.inject_delays(),
+                            "Bot failed to inject, BWAPI did not start in time (missing dependencies, ie. the Visual C++ runtime?)",
+                            || instance.gave_up(),
+                            |access| {
+                                access.clear_stale_slots();
+                                // A new server, other games may free or take slots meanwhile
+                                Ok(access
+                                    .occupied_pids()?
+                                    .iter()
+                                    .any(|pid| !occupied_pids.contains(pid)))
+                            },
+                        )?;
+                        injected
+                            .map_err(|e| {
+                                debug!("Game table: {:?}", game_table_access.snapshot());
+                                match instance.exit_reason() {
+                                    Some(reason) => anyhow!("{}: StarCraft {}", e, reason),
+                                    None => anyhow!(e),
+                                }
+                            })
+                            .with_context(|| BotLaunchFailed(bot.config_name.clone()))?;
+                    }
+
+                    // Out and err of all launches go to the same log
+                    let bot_output = match bot.binary {
+                        Binary::Dll(_) => None,
+                        _ => Some(File::create(&bot_log).with_context(|| {
+                            format!("Could not create '{}'", bot_log.to_string_lossy())
+                        })?),
+                    };
+                    let mut launch_retries = 0;
+                    let clients = index + 1;
+                    instance.bot = bot
+                        .binary
+                        .client_command(sandbox, java_path.as_deref(), &bot.jvm_args)
+                        .map(|ref mut cmd| -> anyhow::Result<Child> {
+                            cmd.current_dir(&bot.working_dir);
+                            loop {
+                                // Wait for server to be ready to accept connections
+                                if uses_game_table {
+                                    game_table_access
+                                        .poll(
+                                            game_config.wait.delays(),
+                                            "Server process not ready in time",
+                                            || interrupted().then_some("Interrupted"),
+                                            |access| {
+                                                access.clear_stale_slots();
+                                                access.has_free_slot()
+                                            },
+                                        )?
+                                        .map_err(|e| anyhow!(e))?;
+                                }
+
+                                cmd.stdout(Stdio::piped());
+                                cmd.stderr(Stdio::piped());
+
+                                let mut child = cmd.spawn()?;
+                                if let Some(bot_output) = &bot_output {
+                                    if let Some(out) = child.stdout.take() {
+                                        tee_output(out, bot_output.try_clone()?, bot.name.clone());
+                                    }
+                                    if let Some(err) = child.stderr.take() {
+                                        tee_output(err, bot_output.try_clone()?, bot.name.clone());
+                                    }
+                                }
+
+                                // Wait up to `max_wait` before bailing, or until the lobby
+                                // timeout (if any) for bots that take their time to get ready
+                                let connect_deadline = if game_config.wait.wait_all_ready {
+                                    lobby_deadline
+                                } else {
+                                    Some(Instant::now() + game_config.wait.max_wait)
+                                };
+                                let delays = game_config.wait.delays_until(connect_deadline);
+                                let connected = game_table_access.poll(
+                                    delays,
+                                    "Bot client executable did not connect to BWAPI server in time (did you try to run a human hosted game without hosting it?)",
+                                    || {
+                                        instance.gave_up().or_else(|| {
+                                            (!matches!(child.try_wait(), Ok(None)))
+                                                .then_some("Bot process died")
+                                        })
+                                    },
+                                    |access| {
+                                        if !uses_game_table {
+                                            return Ok(true);
+                                        }
+                                        access.clear_stale_slots();
+                                        // The ones launched so far, and the one of this bot
+                                        let mut pids = access.occupied_pids()?;
+                                        pids.retain(|pid| {
+                                            game_pids.contains(pid) || !occupied_pids.contains(pid)
+                                        });
+                                        access.expected_connected(&pids, clients)
+                                    },
+                                );
+                                let connected = match connected {
+                                    Ok(connected) => connected,
+                                    Err(e) => {
+                                        // Relaunching the bot won't help
+                                        child.kill().ok();
+                                        return Err(e);
+                                    }
+                                };
+                                match connected {
+                                    Ok(()) => return Ok(child),
+                                    Err(e) => {
+                                        child.kill().ok();
+                                        if uses_game_table {
+                                            debug!(
+                                                "Game table: {:?}",
+                                                game_table_access.snapshot()
+                                            );
+                                        }
+                                        // Without a server, there's nothing to connect to
+                                        if launch_retries >= game_config.launch_retries
+                                            || interrupted()
+                                            || !matches!(instance.bwheadless.try_wait(), Ok(None))
+                                        {
+                                            return Err(match instance.exit_reason() {
+                                                Some(reason) => {
+                                                    anyhow!("{}: StarCraft {}", e, reason)
+                                                }
+                                                None => anyhow!(e),
+                                            });
+                                        }
+                                        launch_retries += 1;
+                                        warn!(
+                                            "'{}' failed to connect ({}), relaunching ({}/{})",
+                                            bot.name, e, launch_retries, game_config.launch_retries
+                                        );
+                                    }
+                                }
+                            }
+                        })
+                        .transpose()
+                        .with_context(|| BotLaunchFailed(bot.config_name.clone()))?;
+                    // StarCraft registers in the game table, the process started is only the launcher
+                    let started_pids: Vec<_> = game_table_access
+                        .occupied_pids()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|pid| uses_game_table && !occupied_pids.contains(pid))
+                        .collect();
+                    game_pids.extend(&started_pids);
+                    if bot.cpu_affinity.is_some() || bot.priority.is_some() {
+                        // The launcher too, injectory (with `--wait-for-exit`) runs until the game ends like bwheadless
+                        let launcher_pid = matches!(instance.bwheadless.try_wait(), Ok(None))
+                            .then(|| instance.bwheadless.id());
+                        let bot_pid = instance.bot.as_ref().map(|bot| bot.id());
+                        for pid in started_pids
+                            .iter()
+                            .copied()
+                            .chain(launcher_pid)
+                            .chain(bot_pid)
+                        {
+                            apply_process_settings(
+                                &bot.name,
+                                bot.cpu_affinity.as_deref(),
+                                bot.priority,
+                                pid,
+                            );
+                        }
+                    }
+                    if let HeadfulMode::Minimized { .. } = bot.headful {
+                        let minimized = !started_pids.is_empty()
+                            && retry(game_config.wait.delays(), || {
+                                if started_pids
+                                    .iter()
+                                    .any(|&pid| window::minimize_windows(pid))
+                                {
+                                    OperationResult::Ok(())
+                                } else {
+                                    OperationResult::Retry(())
+                                }
+                            })
+                            .is_ok();
+                        if !minimized {
+                            warn!("Could not minimize the StarCraft window of '{}'", bot.name);
+                        }
+                    }
+                    instances.push(instance);
+                    played_bots.push(PlayedBot {
+                        name: bot.name,
+                        config_name: bot.config_name,
+                        race: bot.race,
+                        working_dir: bot.working_dir,
+                        replay_dir,
+                        launch_retries,
+                        bwapi_version,
+                        tournament_module: bot.tournament_module.is_some(),
+                        launch_order: index,
+                    });
+                }
+
+                drop(launching);
+                if game_config.dry_run {
+                    info!("Dry run, not starting any games");
+                    return Ok(None);
+                }
+                let mut timings = Timings {
+                    launched: launch_started.elapsed(),
+                    ..Default::default()
+                };
+
+                // Clean up a bit, kill Client bots to prevent them from spamming the slot table
+                // They will also print "Client And Server are not compatible" - if different versions of BWAPI are running with multiple clients
+                let mut wall_clock_timed_out = false;
+                // Only bots with a tournament module leave at `time_out_at_frame`, and the host might not have one
+                let mut frame_limit_reached = None;
+                let mut frame_limit_stopped = false;
+                let mut waiting_for_windows = false;
+                let mut game_started = false;
+                if !played_bots.iter().any(|bot| bot.tournament_module) {
+                    // The frames are only known from the tournament module, BWAPI's game table has none
+                    if game_config.time_out_at_frame.is_some() {
+                        warn!("No bot has a tournament module, 'time_out_at_frame' won't stop the game (see 'time_out_at_seconds')");
+                    }
+                    if lobby_deadline.is_some() {
+                        debug!(
+                            "No bot has a tournament module, can't tell if the game ever starts"
+                        );
+                    }
+                }
+                // Which StarCraft exited in which poll, winners leave last
+                let mut exits = vec![];
+                let mut polls = 0usize;
+                while !instances.is_empty() {
+                    polls += 1;
+                    ensure!(!interrupted(), "Interrupted while the game was running");
+                    if !game_started {
+                        game_started = played_bots
+                            .iter()
+                            .any(|bot| gameresult::last_frame(&bot.working_dir).is_some());
+                        if game_started {
+                            debug!("The game started");
+                            timings.started = Some(launch_started.elapsed());
+                        } else if matches!(lobby_deadline, Some(deadline) if Instant::now() >= deadline)
+                            && played_bots.iter().any(|bot| bot.tournament_module)
+                        {
+                            // Dropping the instances stops them
+                            bail!(
+                                "The game never started: Gave up after {} seconds in the lobby, with all {} bots running (are they in the right menus?)",
+                                lobby_timeout.as_secs(),
+                                played_bots.len()
+                            );
+                        }
+                    }
+                    if let Some(time_out_at_frame) = game_config.time_out_at_frame {
+                        match frame_limit_reached {
+                            None => {
+                                frame_limit_reached = played_bots
+                                    .iter()
+                                    .filter_map(|bot| gameresult::last_frame(&bot.working_dir))
+                                    .max()
+                                    .filter(|frame| *frame >= time_out_at_frame)
+                                    .map(|frame| {
+                                        info!("Reached frame {}, waiting for bots to leave", frame);
+                                        Instant::now()
+                                    });
+                            }
+                            // Give them time to leave, so their replays are saved
+                            Some(reached)
+                                if !frame_limit_stopped
+                                    && reached.elapsed() >= FRAME_TIME_OUT_GRACE =>
+                            {
+                                warn!(
+                                    "Bots did not leave the game at frame {}, stopping it",
+                                    time_out_at_frame
+                                );
+                                frame_limit_stopped = true;
+                                for instance in instances
+                                    .iter_mut()
+                                    .filter(|it| !(game_config.keep_open && it.headful))
+                                {
+                                    instance.bwheadless.kill().ok();
+                                }
+                            }
+                            Some(_) => (),
+                        }
+                    }
+                    if !wall_clock_timed_out
+                        && matches!(deadline, Some(deadline) if Instant::now() >= deadline)
+                    {
+                        warn!(
+                            "Game did not end within {} seconds, stopping it",
+                            game_config.time_out_at_seconds.unwrap_or_default()
+                        );
+                        wall_clock_timed_out = true;
+                        for instance in instances
+                            .iter_mut()
+                            .filter(|it| !(game_config.keep_open && it.headful))
+                        {
+                            instance.bwheadless.kill().ok();
+                        }
+                    }
+                    for i in (0..instances.len()).rev() {
+                        let BotProcess {
+                            ref name,
+                            ref mut bwheadless,
+                            ref mut bot,
+                            observer,
+                            headful,
+                        } = instances[i];
+                        let status = match bwheadless.try_wait() {
+                            Ok(Some(status)) => Some(status),
+                            _ => None,
+                        };
+                        if let Some(status) = status {
+                            // Killed ones fail as well
+                            if !status.success() && !wall_clock_timed_out && !frame_limit_stopped {
+                                warn!("StarCraft of '{}' {}", name, describe_exit_status(status));
+                            }
+                            if let Some(ref mut bot) = bot {
+                                bot.kill().ok();
+                            }
+                            // Kept open windows are closed by hand, whenever one is done looking
+                            let kept_open = game_config.keep_open && headful;
+                            if !observer && !kept_open {
+                                exits.push((polls, name.clone()));
+                            }
+                            instances.swap_remove(i);
+                            info!("{} bots remaining", instances.len());
+                        }
+                    }
+                    if game_config.keep_open
+                        && !waiting_for_windows
+                        && !instances.is_empty()
+                        && instances.iter().all(|it| it.headful)
+                    {
+                        waiting_for_windows = true;
+                        info!("Only StarCraft windows are left, close them when you're done");
+                    }
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+
+                timings.ended = launch_started.elapsed();
+                let last_exited = match exits.as_slice() {
+                    [.., (before, _), (last, name)] if last > before => Some(name.as_str()),
+                    _ => None,
+                };
+                let mut result = GameResult::new(
+                    game_config.map.clone(),
+                    game_config
+                        .map
+                        .as_deref()
+                        .map(|it| map::map_title(&starcraft_path.join(it))),
+                    game_config.time_out_at_frame,
+                    wall_clock_timed_out,
+                    played_bots
+                        .into_iter()
+                        .map(|bot| {
+                            let mut result = BotResult::collect(
+                                bot.name,
+                                bot.race,
+                                &bot.working_dir,
+                                &bot.replay_dir,
+                                started,
+                                game_config.slow_frame_ms,
+                            );
+                            result.config_name = bot.config_name;
+                            result.launch_retries = bot.launch_retries;
+                            result.launch_order = bot.launch_order;
+                            result.bwapi_version = bot.bwapi_version.to_string();
+                            result
+                        })
+                        .collect(),
+                );
+                result.timings = timings;
+                result.infer_winner(last_exited);
+                if game_config.compress_replays {
+                    result.compress_replays();
+                }
+                match &result.winner {
+                    Some(winner) => info!("'{}' won", winner),
+                    None if result.timeout.is_some() => info!(
+                        "Game timed out at frame {}, it's a draw",
+                        result.frame_count.unwrap_or_default()
+                    ),
+                    None => info!("Could not determine a winner"),
+                }
+                for bot in &result.bots {
+                    match bot.slow_frames {
+                        Some(slow_frames) if slow_frames > 0 => warn!(
+                            "'{}' took more than {}ms for {} frames",
+                            bot.name, game_config.slow_frame_ms, slow_frames
+                        ),
+                        _ => (),
+                    }
+                }
+                Ok(Some(result))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::botsetup::test::bot_setup;
+    use crate::botsetup::Binary;
+    use crate::sandbox::SandboxMode;
+    use crate::{
+        bwapi_launcher, check, order_slots, plan_hosts, plays_sound, version_problems, AutoMenu,
+        BwapiConnectMode, BwapiVersion, GameConfig, GameType, HeadfulMode, Race, Shotgun,
+        StarcraftFlavor,
+    };
+
+    /// `(min_players, max_players)` of a headful bot hosting a game for `player_count` bots
+    fn host_players(game_config: &GameConfig, player_count: usize) -> (usize, usize) {
+        let headful = HeadfulMode::On {
+            no_wmode: false,
+            no_sound: false,
+            resolution: None,
+        };
+        let launcher = bwapi_launcher(
+            game_config,
+            bot_setup("A"),
+            headful,
+            true,
+            "shotgun",
+            player_count,
+        )
+        .unwrap();
+        match launcher.bwapi_ini().auto_menu {
+            AutoMenu::Lan {
+                connect_mode:
+                    BwapiConnectMode::Host {
+                        min_players,
+                        max_players,
+                        ..
+                    },
+                ..
+            } => (min_players, max_players),
+            auto_menu => panic!("Not hosting: {:?}", auto_menu),
+        }
+    }
+
+    #[test]
+    fn test_template_game_config() {
+        let game_config: GameConfig = toml::from_str(include_str!("../game.toml")).unwrap();
+        game_config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_headful_resolution() {
+        let game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [{ name = 'A', headful = { On = { resolution = [1280, 960] } } }] }
+            map = 'maps/(4)Python.scx'",
+        )
+        .unwrap();
+        game_config.validate().unwrap();
+        let GameType::Melee(bots) = &game_config.game_type;
+        let launcher = bwapi_launcher(
+            &game_config,
+            bot_setup("A"),
+            bots[0].headful,
+            true,
+            "shotgun",
+            1,
+        )
+        .unwrap();
+        let mut ini = vec![];
+        launcher.bwapi_ini().write(&mut ini).unwrap();
+        let ini = String::from_utf8(ini).unwrap().replace("\r\n", "\n");
+        assert!(ini.contains("[window]\nwindowed = ON\nwidth = 1280\nheight = 960\n"));
+    }
+
+    #[test]
+    fn test_jvm_args() {
+        let jvm_args = vec!["-Xmx2g".to_string()];
+        let cmd = Binary::Jar(PathBuf::from("bot.jar"))
+            .client_command(&SandboxMode::NoSandbox, Some("java"), &jvm_args)
+            .unwrap();
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            ["-Xmx2g", "-jar", "bot.jar"]
+        );
+        let cmd = Binary::Exe(PathBuf::from("bot.exe"))
+            .client_command(&SandboxMode::NoSandbox, None, &jvm_args)
+            .unwrap();
+        assert_eq!(cmd.get_args().count(), 0);
+    }
+
+    #[test]
+    fn test_observer_not_needed() {
+        let game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [{ name = 'A' }, { name = 'B' }, { name = 'Referee', observer = true }] }
+            map = 'maps/(4)Python.scx'",
+        )
+        .unwrap();
+        game_config.validate().unwrap();
+        assert_eq!(host_players(&game_config, 3), (2, 3));
+        // bwheadless just waits for everyone
+        assert!(bwapi_launcher(
+            &game_config,
+            bot_setup("A"),
+            HeadfulMode::Off,
+            true,
+            "shotgun",
+            3
+        )
+        .is_ok());
+
+        let only_observers: GameConfig = toml::from_str(
+            "game_type = { Melee = [{ name = 'Referee', observer = true }] }
+            map = 'maps/(4)Python.scx'",
+        )
+        .unwrap();
+        assert!(only_observers.validate().is_err());
+    }
+
+    #[test]
+    fn test_wait_all_ready() {
+        let mut game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [{ name = 'A' }, { name = 'B' }, { name = 'Referee', observer = true }] }
+            map = 'maps/(4)Python.scx'
+            [wait]
+            wait_all_ready = true",
+        )
+        .unwrap();
+        game_config.validate().unwrap();
+        // The observer is waited for as well
+        assert_eq!(host_players(&game_config, 3), (3, 3));
+        game_config.min_players = Some(2);
+        assert!(game_config.validate().is_err());
+    }
+
+    #[test]
+    fn test_host_player_range() {
+        let mut game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [] }
+            map = 'maps/(4)Python.scx'
+            min_players = 2
+            max_players = 4",
+        )
+        .unwrap();
+        game_config.validate().unwrap();
+        let headful = HeadfulMode::On {
+            no_wmode: false,
+            no_sound: false,
+            resolution: None,
+        };
+        assert_eq!(host_players(&game_config, 3), (2, 4));
+        // More bots than the host waits for
+        assert!(bwapi_launcher(&game_config, bot_setup("A"), headful, true, "shotgun", 5).is_err());
+        // bwheadless always waits for all players
+        assert!(bwapi_launcher(
+            &game_config,
+            bot_setup("A"),
+            HeadfulMode::Off,
+            true,
+            "shotgun",
+            3
+        )
+        .is_err());
+
+        game_config.min_players = Some(5);
+        assert!(game_config.validate().is_err());
+        game_config.min_players = Some(0);
+        assert!(game_config.validate().is_err());
+    }
+
+    #[test]
+    fn test_game_name() {
+        let mut game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [] }
+            map = 'maps/(2)Destination.scx'
+            game_name = 'Shotgun Lobby'",
+        )
+        .unwrap();
+        game_config.validate().unwrap();
+        for game_name in ["", " ", "ABCDEFGHIJKLMNOPQRSTUVWXY", "Lobby\u{e9}"] {
+            game_config.game_name = Some(game_name.to_string());
+            assert!(game_config.validate().is_err(), "{}", game_name);
+        }
+    }
+
+    #[test]
+    fn test_sound() {
+        let mut game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [{ name = 'A' }] }
+            map = 'maps/(4)Python.scx'",
+        )
+        .unwrap();
+        let quiet = HeadfulMode::On {
+            no_wmode: false,
+            no_sound: true,
+            resolution: None,
+        };
+        assert!(!plays_sound(&game_config, quiet));
+        assert!(plays_sound(
+            &game_config,
+            HeadfulMode::Minimized { no_sound: false }
+        ));
+        assert!(!plays_sound(&game_config, HeadfulMode::Off));
+        game_config.sound = Some(true);
+        assert!(plays_sound(&game_config, quiet));
+        game_config.sound = Some(false);
+        assert!(!plays_sound(
+            &game_config,
+            HeadfulMode::Minimized { no_sound: false }
+        ));
+    }
+
+    #[test]
+    fn test_keep_open_needs_a_window() {
+        let mut game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [{ name = 'A' }, { name = 'B', headful = { Minimized = {} } }] }
+            map = 'maps/(2)Destination.scx'
+            keep_open = true",
+        )
+        .unwrap();
+        game_config.validate().unwrap();
+        let GameType::Melee(bots) = &mut game_config.game_type;
+        bots[1].headful = HeadfulMode::Off;
+        assert!(game_config.validate().is_err());
+    }
+
+    #[test]
+    fn test_human_host_bots_join() {
+        let mut game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [] }
+            human_host = true",
+        )
+        .unwrap();
+        for flavor in [StarcraftFlavor::Retail, StarcraftFlavor::OpenBW] {
+            game_config.starcraft_flavor = flavor;
+            for headful in [
+                HeadfulMode::Off,
+                HeadfulMode::On {
+                    no_wmode: false,
+                    no_sound: false,
+                    resolution: None,
+                },
+                HeadfulMode::Minimized { no_sound: false },
+            ] {
+                let launcher =
+                    bwapi_launcher(&game_config, bot_setup("A"), headful, false, "shotgun", 2)
+                        .unwrap();
+                match launcher.bwapi_ini().auto_menu {
+                    // bwheadless joins by itself, it's only told to host
+                    AutoMenu::Unused => assert!(
+                        flavor == StarcraftFlavor::Retail && matches!(headful, HeadfulMode::Off)
+                    ),
+                    AutoMenu::Lan {
+                        connect_mode,
+                        game_name,
+                        ..
+                    } => {
+                        assert!(matches!(connect_mode, BwapiConnectMode::Join));
+                        assert_eq!(game_name, "JOIN_FIRST");
+                    }
+                    AutoMenu::SinglePlayer { .. } => panic!("Joining isn't single player"),
+                }
+                assert!(
+                    bwapi_launcher(&game_config, bot_setup("A"), headful, true, "shotgun", 2)
+                        .is_err()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_race_from_str() {
+        for (spellings, race) in [
+            (["t", "T", "terran", "Terran"], Race::Terran),
+            (["p", "P", "protoss", "PROTOSS"], Race::Protoss),
+            (["z", "Z", "zerg", "Zerg"], Race::Zerg),
+            (["r", "R", "random", "Random"], Race::Random),
+        ] {
+            for spelling in spellings {
+                assert_eq!(spelling.parse::<Race>().unwrap(), race, "{}", spelling);
+            }
+        }
+        let err = "x".parse::<Race>().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Invalid race 'x', expected one of Zerg/Protoss/Terran/Random or z/p/t/r"
+        );
+
+        let toml_err =
+            toml::from_str::<GameConfig>("game_type = { Melee = [{ name = 'A', race = 'x' }] }")
+                .unwrap_err()
+                .to_string();
+        assert!(toml_err.contains("one of Zerg/Protoss/Terran/Random or z/p/t/r"));
+    }
+
+    #[test]
+    fn test_game_config_summary() {
+        let config = toml::from_str::<GameConfig>(
+            "map = 'maps/Fighting Spirit.scx'\n\
+             time_out_at_frame = 20000\n\
+             game_type = { Melee = [{ name = 'A', race = 'p', headful = 'Off' }, { name = 'B', headful = { Minimized = {} } }] }",
+        )
+        .unwrap();
+        assert_eq!(
+            config.to_string(),
+            "Map: maps/Fighting Spirit.scx\n\
+             Game: hosted by a bot, Retail, Local PC, 3 latency frames, speed BWAPI's default\n\
+             Timeouts: frame 20000, - seconds\n\
+             Bots:\n  A (Protoss)\n  B (race of bot.toml, minimized)"
+        );
+    }
+
+    #[test]
+    fn test_version_problems() {
+        let exe = Path::new(r"C:\StarCraft\StarCraft.exe");
+        let bots = [
+            ("A", BwapiVersion::Bwapi420),
+            ("B", BwapiVersion::Unknown(0xDEADBEEF)),
+            ("C", BwapiVersion::Bwapi440),
+        ];
+        // Not a StarCraft.exe at all
+        let not_starcraft = Some((exe, &b"not starcraft"[..]));
+        let problems = version_problems(not_starcraft, &bots, true);
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("BWAPI 420 of 'A' needs StarCraft 1.16.1"));
+        assert_eq!(problems[1], "'B' has an unknown BWAPI.dll (CRC 0xDEADBEEF)");
+        assert_eq!(version_problems(not_starcraft, &bots, false).len(), 1);
+        assert!(version_problems(Some((exe, b"")), &bots[1..2], false).is_empty());
+        // OpenBW, there's no StarCraft.exe to check, but unknown BWAPI.dlls still fail
+        assert_eq!(
+            version_problems(None, &bots, true),
+            ["'B' has an unknown BWAPI.dll (CRC 0xDEADBEEF)"]
+        );
+        assert!(version_problems(None, &bots, false).is_empty());
+    }
+
+    #[test]
+    fn test_order_slots() {
+        let dll = |bot: &(&str, bool)| bot.1;
+        let mut bots = [("D1", true), ("C1", false), ("D2", true), ("C2", false)];
+        let mut rng = StdRng::seed_from_u64(42);
+        order_slots(&mut bots, dll, false, &mut rng);
+        assert_eq!(
+            bots.map(|bot| bot.0),
+            ["C1", "C2", "D1", "D2"],
+            "Clients first, otherwise as configured"
+        );
+        let orders: HashSet<_> = (0..20)
+            .map(|_| {
+                order_slots(&mut bots, dll, true, &mut rng);
+                assert!(!bots[0].1 && !bots[1].1);
+                bots.map(|bot| bot.0)
+            })
+            .collect();
+        assert_eq!(orders.len(), 4);
+
+        let shuffled = |seed| {
+            let mut bots = [("A", true), ("B", true), ("C", true), ("D", true)];
+            order_slots(&mut bots, dll, true, &mut StdRng::seed_from_u64(seed));
+            bots
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+    }
+
+    #[test]
+    fn test_check_without_starcraft() {
+        let no_starcraft = std::env::temp_dir().join("bwaishotgun-test-no-starcraft");
+        assert!(Shotgun::with_starcraft_dir(Some(&no_starcraft)).is_err());
+        let mut shotgun = Shotgun::with_any_starcraft_dir(Some(&no_starcraft)).unwrap();
+        assert_eq!(shotgun.starcraft_path, no_starcraft);
+        let game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [] }
+            map = 'maps/(4)Python.scx'",
+        )
+        .unwrap();
+        // Failing the checklist, instead of not getting to it
+        assert!(check::run(&mut shotgun, &game_config).is_err());
+    }
+
+    #[test]
+    fn test_plan_hosts() {
+        assert_eq!(plan_hosts(2, false).unwrap(), vec![true, false]);
+        assert_eq!(plan_hosts(2, true).unwrap(), vec![false, false]);
+        // Bots might join a human later
+        assert!(plan_hosts(0, true).unwrap().is_empty());
+        // No bots and no human: Nobody would create the game
+        let game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [] }
+            map = 'maps/(4)Python.scx'",
+        )
+        .unwrap();
+        game_config.validate().unwrap();
+        assert!(plan_hosts(0, game_config.human_host).is_err());
+    }
+
+    #[test]
+    fn test_unzip_bot() {
+        let dir = std::env::temp_dir().join("bwaishotgun-test-unzip-bot");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let zip = dir.join("NitekatT.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&zip).unwrap());
+        for file in [
+            "NitekatT/BWAPI.dll",
+            "NitekatT/AI/NitekatT.dll",
+            "NitekatT/read/x",
+        ] {
+            writer.start_file(file, Default::default()).unwrap();
+            writer.write_all(b"MZ").unwrap();
+        }
+        writer.finish().unwrap();
+
+        let target = dir.join("unzipped");
+        let (working_dir, binary) = Binary::unzip(&zip, &target).unwrap();
+        assert_eq!(working_dir, target.join("NitekatT"));
+        match binary {
+            Binary::Dll(dll) => assert_eq!(dll, target.join("NitekatT/AI/NitekatT.dll")),
+            binary => panic!("Expected the AI module, got {:?}", binary),
+        }
+        assert!(target.join("NitekatT/read/x").is_file());
+    }
+}
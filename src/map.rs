@@ -0,0 +1,381 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure};
+use log::debug;
+use rand::Rng;
+
+use crate::mpq;
+
+const MAP_EXTENSIONS: [&str; 3] = ["scx", "scm", "sc"];
+
+/// Unit type of start locations in the `UNIT` section of a `scenario.chk`
+const START_LOCATION: u16 = 214;
+const UNIT_LEN: usize = 36;
+
+pub fn is_map_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            MAP_EXTENSIONS.contains(&ext.as_str())
+        })
+        .unwrap_or(false)
+}
+
+/// Ensures `path` is an existing Brood War map
+pub fn validate_map(path: &Path) -> anyhow::Result<()> {
+    ensure!(
+        path.exists(),
+        "Map '{}' does not exist",
+        path.to_string_lossy()
+    );
+    ensure!(
+        path.is_file(),
+        "Map '{}' is not a file",
+        path.to_string_lossy()
+    );
+    ensure!(
+        is_map_file(path),
+        "Map '{}' is not a Brood War map ({})",
+        path.to_string_lossy(),
+        MAP_EXTENSIONS.map(|ext| format!(".{}", ext)).join("/")
+    );
+    Ok(())
+}
+
+/// Finds `map` in the `maps` folder of StarCraft or in StarCraft itself, absolute paths are only validated.
+/// The result is relative to `starcraft_path` (unless absolute), like the maps of `pick_map`.
+pub fn resolve_map(starcraft_path: &Path, map: &str) -> anyhow::Result<PathBuf> {
+    let path = Path::new(map);
+    if path.has_root() || path.is_absolute() {
+        validate_map(path)?;
+        return Ok(path.to_path_buf());
+    }
+    let candidates = [Path::new("maps").join(path), path.to_path_buf()];
+    let found = candidates
+        .iter()
+        .find(|candidate| starcraft_path.join(candidate).exists());
+    match found {
+        Some(found) => {
+            validate_map(&starcraft_path.join(found))?;
+            Ok(found.clone())
+        }
+        None => bail!(
+            "Could not find map '{}', neither as '{}' nor as '{}'",
+            map,
+            starcraft_path.join(&candidates[0]).to_string_lossy(),
+            starcraft_path.join(&candidates[1]).to_string_lossy()
+        ),
+    }
+}
+
+/// Title of the map as shown in StarCraft, or its file name if it can't be read
+pub fn map_title(path: &Path) -> String {
+    let title = std::fs::read(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|map| mpq::read_file(&map, "staredit\\scenario.chk"))
+        .and_then(|chk| scenario_name(&chk));
+    match title {
+        Ok(title) if !title.is_empty() => title,
+        result => {
+            if let Err(e) = result {
+                debug!(
+                    "Could not read the title of '{}': {}",
+                    path.to_string_lossy(),
+                    e
+                );
+            }
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .to_string()
+        }
+    }
+}
+
+/// Number of start locations of the map (how many players it's for)
+pub fn start_locations(path: &Path) -> anyhow::Result<usize> {
+    let chk = mpq::read_file(&std::fs::read(path)?, "staredit\\scenario.chk")?;
+    let sections = chk_sections(&chk);
+    let units = match sections.get(&b"UNIT"[..]) {
+        Some(units) => units,
+        None => bail!("The map has no units"),
+    };
+    Ok(units
+        .chunks_exact(UNIT_LEN)
+        .filter(|unit| u16::from_le_bytes([unit[8], unit[9]]) == START_LOCATION)
+        .count())
+}
+
+/// Sections of a `scenario.chk` by name
+fn chk_sections(chk: &[u8]) -> std::collections::HashMap<&[u8], &[u8]> {
+    let mut sections = std::collections::HashMap::new();
+    let mut offset = 0;
+    while let Some(header) = chk.get(offset..offset + 8) {
+        let size = i32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let start = offset + 8;
+        // Negative sizes are a trick of map protectors, like StarCraft just skip over them
+        let end = start.saturating_add_signed(size as isize).min(chk.len());
+        // Like StarCraft, the last section of a name wins
+        sections.insert(&header[..4], chk.get(start..end).unwrap_or_default());
+        if end <= offset {
+            break;
+        }
+        offset = end;
+    }
+    sections
+}
+
+/// The scenario name of a `scenario.chk` (string `SPRP` points to in `STR ` or `STRx`)
+fn scenario_name(chk: &[u8]) -> anyhow::Result<String> {
+    let sections = chk_sections(chk);
+    let u16_at = |data: &[u8], at: usize| {
+        data.get(at..at + 2)
+            .map(|it| u16::from_le_bytes([it[0], it[1]]) as usize)
+    };
+    let u32_at = |data: &[u8], at: usize| {
+        data.get(at..at + 4)
+            .map(|it| u32::from_le_bytes([it[0], it[1], it[2], it[3]]) as usize)
+    };
+    let name_index = match sections.get(&b"SPRP"[..]).and_then(|sprp| u16_at(sprp, 0)) {
+        Some(index) if index > 0 => index,
+        _ => bail!("The map has no scenario name"),
+    };
+    let start = if let Some(strings) = sections.get(&b"STRx"[..]) {
+        u32_at(strings, name_index * 4).map(|start| (*strings, start))
+    } else if let Some(strings) = sections.get(&b"STR "[..]) {
+        u16_at(strings, name_index * 2).map(|start| (*strings, start))
+    } else {
+        None
+    };
+    let (strings, start) = match start {
+        Some(start) => start,
+        None => bail!("The map has no string for its scenario name"),
+    };
+    let string = strings.get(start..).unwrap_or_default();
+    let string: Vec<u8> = string
+        .iter()
+        .take_while(|&&b| b != 0)
+        // Colors and other formatting
+        .filter(|&&b| b >= 0x20 && b != 0x7F)
+        .copied()
+        .collect();
+    Ok(match String::from_utf8(string) {
+        Ok(utf8) => utf8,
+        // Older maps are Latin-1 (or whatever the editor used)
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    }
+    .trim()
+    .to_string())
+}
+
+/// Collects all maps in the given files/folders (recursively), anything else is skipped
+pub fn collect_maps(path: &Path, maps: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if path.is_dir() {
+        for entry in path.read_dir()?.flatten() {
+            collect_maps(&entry.path(), maps)?;
+        }
+    } else if path.is_file() && is_map_file(path) {
+        maps.push(path.to_path_buf());
+    } else {
+        debug!("Skipping '{}', not a map", path.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Whether a map with `start_locations` fits a game of `players` (exactly, or with room to spare)
+fn fits(start_locations: usize, players: usize, exact: bool) -> bool {
+    start_locations == players || !exact && start_locations > players
+}
+
+/// Picks a random map of the pool for `players`, paths are relative to `starcraft_path` (if not absolute)
+pub fn pick_map(
+    starcraft_path: &Path,
+    map_pool: &[PathBuf],
+    players: usize,
+    exact: bool,
+    rng: &mut impl Rng,
+) -> anyhow::Result<PathBuf> {
+    let mut maps = vec![];
+    for entry in map_pool {
+        collect_maps(&starcraft_path.join(entry), &mut maps)?;
+    }
+    ensure!(
+        !maps.is_empty(),
+        "No maps ({}) found in map pool: {:?}",
+        MAP_EXTENSIONS.map(|ext| format!(".{}", ext)).join("/"),
+        map_pool
+    );
+    let found = maps.len();
+    maps.retain(|map| match start_locations(map) {
+        Ok(start_locations) if fits(start_locations, players, exact) => true,
+        Ok(start_locations) => {
+            debug!(
+                "Skipping '{}', it's for {} players",
+                map.to_string_lossy(),
+                start_locations
+            );
+            false
+        }
+        // Might still work, StarCraft will tell
+        Err(e) => {
+            debug!(
+                "Could not read the start locations of '{}': {}",
+                map.to_string_lossy(),
+                e
+            );
+            true
+        }
+    });
+    ensure!(
+        !maps.is_empty(),
+        "None of the {} maps in the map pool is for {} players: {:?}",
+        found,
+        players,
+        map_pool
+    );
+    // Directory listings are not ordered, but seeded picks should be reproducible
+    maps.sort();
+    let map = &maps[rng.gen_range(0..maps.len())];
+    Ok(map
+        .strip_prefix(starcraft_path)
+        .unwrap_or(map)
+        .to_path_buf())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::map::{
+        fits, is_map_file, map_title, resolve_map, scenario_name, start_locations, validate_map,
+        START_LOCATION,
+    };
+    use std::fs::{create_dir_all, File};
+    use std::path::Path;
+
+    #[test]
+    fn test_map_extensions() {
+        assert!(is_map_file(Path::new(r"maps\(2)Destination.scx")));
+        assert!(is_map_file(Path::new("Fighting Spirit.SCM")));
+        assert!(!is_map_file(Path::new("readme.txt")));
+        assert!(!is_map_file(Path::new("maps")));
+    }
+
+    #[test]
+    fn test_validate_map() {
+        let err = validate_map(Path::new("test-resources/missing.scx")).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+        let err = validate_map(Path::new("test-resources")).unwrap_err();
+        assert!(err.to_string().contains("not a file"));
+        let err = validate_map(Path::new("test-resources/BWAPI440.dll")).unwrap_err();
+        assert!(err.to_string().contains("not a Brood War map"));
+    }
+
+    fn chk(sections: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut chk = Vec::new();
+        for (name, data) in sections {
+            chk.extend(*name);
+            chk.extend((data.len() as u32).to_le_bytes());
+            chk.extend(*data);
+        }
+        chk
+    }
+
+    #[test]
+    fn test_scenario_name() {
+        // 2 strings: "Desc" and "\x03Fighting Spirit " (with color)
+        let strings = b"\x02\x00\x06\x00\x0B\x00Desc\x00\x03Fighting Spirit \x00";
+        let chk_bytes = chk(&[
+            (b"VER ", &[205, 0]),
+            (b"SPRP", &[1, 0, 2, 0]),
+            (b"STR ", b"\x01\x00\x04\x00Old\x00"),
+            // Later sections replace earlier ones
+            (b"SPRP", &[2, 0, 1, 0]),
+            (b"STR ", strings),
+        ]);
+        assert_eq!(scenario_name(&chk_bytes).unwrap(), "Fighting Spirit");
+
+        let chk_bytes = chk(&[
+            (b"SPRP", &[1, 0, 0, 0]),
+            (b"STRx", b"\x01\x00\x00\x00\x08\x00\x00\x00Caf\xE9\x00"),
+        ]);
+        assert_eq!(scenario_name(&chk_bytes).unwrap(), "Caf\u{e9}");
+
+        assert!(scenario_name(&chk(&[(b"SPRP", &[0, 0, 0, 0])])).is_err());
+        assert!(scenario_name(&chk(&[(b"SPRP", &[1, 0, 0, 0])])).is_err());
+        assert!(scenario_name(b"junk").is_err());
+    }
+
+    #[test]
+    fn test_map_title() {
+        let dir = std::env::temp_dir().join("bwaishotgun-test-map-title");
+        create_dir_all(&dir).unwrap();
+        let chk_bytes = chk(&[
+            (b"SPRP", &[1, 0, 0, 0]),
+            (b"STR ", b"\x01\x00\x04\x00Destination\x00"),
+        ]);
+        let map = crate::mpq::test::archive(
+            "staredit\\scenario.chk",
+            // Exists, single unit
+            0x8100_0000,
+            &chk_bytes,
+            chk_bytes.len(),
+        );
+        std::fs::write(dir.join("(2)Destination 1.1.scx"), map).unwrap();
+        assert_eq!(
+            map_title(&dir.join("(2)Destination 1.1.scx")),
+            "Destination"
+        );
+
+        // A start location, and a unit of player 2 (a Probe)
+        let mut units = vec![0u8; 2 * 36];
+        units[8..10].copy_from_slice(&START_LOCATION.to_le_bytes());
+        units[36 + 8..36 + 10].copy_from_slice(&64u16.to_le_bytes());
+        let chk_bytes = chk(&[(b"UNIT", &units)]);
+        let map = crate::mpq::test::archive(
+            "staredit\\scenario.chk",
+            0x8100_0000,
+            &chk_bytes,
+            chk_bytes.len(),
+        );
+        std::fs::write(dir.join("(1)Alone.scm"), map).unwrap();
+        assert_eq!(start_locations(&dir.join("(1)Alone.scm")).unwrap(), 1);
+        assert!(start_locations(&dir.join("(2)Destination 1.1.scx")).is_err());
+
+        File::create(dir.join("(2)Broken.scx")).unwrap();
+        assert_eq!(map_title(&dir.join("(2)Broken.scx")), "(2)Broken.scx");
+        assert_eq!(map_title(&dir.join("(2)Missing.scx")), "(2)Missing.scx");
+    }
+
+    #[test]
+    fn test_fits() {
+        assert!(fits(2, 2, true));
+        assert!(fits(4, 2, false));
+        assert!(!fits(4, 2, true));
+        assert!(!fits(2, 3, false));
+    }
+
+    #[test]
+    fn test_resolve_map() {
+        let starcraft_path = std::env::temp_dir().join("bwaishotgun-test-resolve-map");
+        create_dir_all(starcraft_path.join("maps/BroodWar")).unwrap();
+        File::create(starcraft_path.join("maps/BroodWar/(2)Test.scx")).unwrap();
+
+        let relative_to_maps = Path::new("maps").join("BroodWar/(2)Test.scx");
+        assert_eq!(
+            resolve_map(&starcraft_path, "BroodWar/(2)Test.scx").unwrap(),
+            relative_to_maps
+        );
+        // Relative to StarCraft, as before
+        assert_eq!(
+            resolve_map(&starcraft_path, "maps/BroodWar/(2)Test.scx").unwrap(),
+            Path::new("maps/BroodWar/(2)Test.scx")
+        );
+        let absolute = starcraft_path.join("maps/BroodWar/(2)Test.scx");
+        assert_eq!(
+            resolve_map(&starcraft_path, &absolute.to_string_lossy()).unwrap(),
+            absolute
+        );
+        let err = resolve_map(&starcraft_path, "Missing.scx").unwrap_err();
+        assert!(err.to_string().contains("neither as"));
+        assert!(err.to_string().contains("Missing.scx"));
+    }
+}
@@ -0,0 +1,480 @@
+//! Just enough of the MPQ format to read the `scenario.chk` of a map (no writing, no listfiles)
+
+use anyhow::{bail, ensure, Context};
+
+const HEADER_MAGIC: &[u8; 4] = b"MPQ\x1A";
+const HASH_ENTRY_EMPTY: u32 = 0xFFFF_FFFF;
+
+const FILE_IMPLODE: u32 = 0x0000_0100;
+const FILE_COMPRESS: u32 = 0x0000_0200;
+const FILE_ENCRYPTED: u32 = 0x0001_0000;
+const FILE_FIX_KEY: u32 = 0x0002_0000;
+const FILE_SINGLE_UNIT: u32 = 0x0100_0000;
+const FILE_EXISTS: u32 = 0x8000_0000;
+
+/// Compression mask of sectors with `FILE_COMPRESS`
+const COMPRESSION_PKWARE: u8 = 0x08;
+
+enum HashType {
+    TableOffset = 0,
+    NameA = 0x100,
+    NameB = 0x200,
+    FileKey = 0x300,
+}
+
+fn crypt_table() -> [u32; 0x500] {
+    let mut table = [0; 0x500];
+    let mut seed: u32 = 0x0010_0001;
+    for index in 0..0x100 {
+        for i in 0..5 {
+            seed = (seed * 125 + 3) % 0x2A_AAAB;
+            let high = (seed & 0xFFFF) << 16;
+            seed = (seed * 125 + 3) % 0x2A_AAAB;
+            table[index + i * 0x100] = high | (seed & 0xFFFF);
+        }
+    }
+    table
+}
+
+struct Crypt {
+    table: [u32; 0x500],
+}
+
+impl Crypt {
+    fn new() -> Self {
+        Self {
+            table: crypt_table(),
+        }
+    }
+
+    fn hash(&self, name: &str, hash_type: HashType) -> u32 {
+        let offset = hash_type as usize;
+        let (mut seed1, mut seed2): (u32, u32) = (0x7FED_7FED, 0xEEEE_EEEE);
+        for c in name.bytes().map(|c| c.to_ascii_uppercase()) {
+            seed1 = self.table[offset + c as usize] ^ seed1.wrapping_add(seed2);
+            seed2 = (c as u32)
+                .wrapping_add(seed1)
+                .wrapping_add(seed2)
+                .wrapping_add(seed2 << 5)
+                .wrapping_add(3);
+        }
+        seed1
+    }
+
+    /// Decrypts whole DWORDs, trailing bytes are not encrypted
+    fn decrypt(&self, data: &mut [u8], mut key: u32) {
+        let mut seed: u32 = 0xEEEE_EEEE;
+        for chunk in data.chunks_exact_mut(4) {
+            seed = seed.wrapping_add(self.table[0x400 + (key & 0xFF) as usize]);
+            let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])
+                ^ key.wrapping_add(seed);
+            key = ((!key << 21).wrapping_add(0x1111_1111)) | (key >> 11);
+            seed = value
+                .wrapping_add(seed)
+                .wrapping_add(seed << 5)
+                .wrapping_add(3);
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("Unexpected end of the archive")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads the file `name` (ie. `staredit\scenario.chk`) of the MPQ archive `archive`
+pub fn read_file(archive: &[u8], name: &str) -> anyhow::Result<Vec<u8>> {
+    let start = (0..archive.len())
+        .step_by(512)
+        .find(|&offset| archive[offset..].starts_with(HEADER_MAGIC))
+        .context("Not an MPQ archive")?;
+    let mpq = &archive[start..];
+    // The sector shift is the upper half, after the format version
+    let sector_size = 512usize << (u32_at(mpq, 12)? >> 16).min(16);
+    let hash_table_offset = u32_at(mpq, 16)? as usize;
+    let block_table_offset = u32_at(mpq, 20)? as usize;
+    let hash_table_len = u32_at(mpq, 24)? as usize;
+    let block_table_len = u32_at(mpq, 28)? as usize;
+
+    let crypt = Crypt::new();
+    let table = |offset: usize, len: usize, key: &str| -> anyhow::Result<Vec<u8>> {
+        // Tables might be cut short (ie. by map protectors)
+        let mut table = mpq
+            .get(offset..)
+            .context("Table outside of the archive")?
+            .iter()
+            .copied()
+            .take(len.saturating_mul(16))
+            .collect::<Vec<_>>();
+        crypt.decrypt(&mut table, crypt.hash(key, HashType::FileKey));
+        Ok(table)
+    };
+    let hash_table = table(hash_table_offset, hash_table_len, "(hash table)")?;
+    let block_table = table(block_table_offset, block_table_len, "(block table)")?;
+    let hash_table_len = hash_table.len() / 16;
+    ensure!(hash_table_len > 0, "The archive has no hash table");
+
+    let (name_a, name_b) = (
+        crypt.hash(name, HashType::NameA),
+        crypt.hash(name, HashType::NameB),
+    );
+    let first = crypt.hash(name, HashType::TableOffset) as usize % hash_table_len;
+    let mut block_index = None;
+    for i in 0..hash_table_len {
+        let entry = (first + i) % hash_table_len * 16;
+        let index = u32_at(&hash_table, entry + 12)?;
+        if index == HASH_ENTRY_EMPTY {
+            break;
+        }
+        if u32_at(&hash_table, entry)? == name_a && u32_at(&hash_table, entry + 4)? == name_b {
+            block_index = Some(index as usize);
+            break;
+        }
+    }
+    let block = block_index.context("File not found in the archive")? * 16;
+    let position = u32_at(&block_table, block)? as usize;
+    let compressed_size = u32_at(&block_table, block + 4)? as usize;
+    let size = u32_at(&block_table, block + 8)? as usize;
+    let flags = u32_at(&block_table, block + 12)?;
+    ensure!(
+        flags & FILE_EXISTS != 0,
+        "File was deleted from the archive"
+    );
+    let data = mpq
+        .get(position..position.saturating_add(compressed_size))
+        .context("File outside of the archive")?;
+
+    let mut key = 0;
+    if flags & FILE_ENCRYPTED != 0 {
+        let file_name = name.rsplit('\\').next().unwrap_or(name);
+        key = crypt.hash(file_name, HashType::FileKey);
+        if flags & FILE_FIX_KEY != 0 {
+            key = key.wrapping_add(position as u32) ^ size as u32;
+        }
+    }
+    let compressed = flags & (FILE_IMPLODE | FILE_COMPRESS) != 0;
+    let sectors: Vec<(usize, usize)> = if flags & FILE_SINGLE_UNIT != 0 {
+        vec![(0, compressed_size)]
+    } else if compressed {
+        // Each sector is compressed on its own, the table tells where they are
+        let sector_count = size.div_ceil(sector_size);
+        let mut offsets = data
+            .get(..(sector_count + 1) * 4)
+            .context("Sector table outside of the archive")?
+            .to_vec();
+        if flags & FILE_ENCRYPTED != 0 {
+            crypt.decrypt(&mut offsets, key.wrapping_sub(1));
+        }
+        let offsets: Vec<usize> = offsets
+            .chunks_exact(4)
+            .map(|it| u32::from_le_bytes([it[0], it[1], it[2], it[3]]) as usize)
+            .collect();
+        offsets.windows(2).map(|it| (it[0], it[1])).collect()
+    } else {
+        (0..compressed_size)
+            .step_by(sector_size)
+            .map(|start| (start, (start + sector_size).min(compressed_size)))
+            .collect()
+    };
+
+    let mut file = Vec::with_capacity(size);
+    for (index, &(start, end)) in sectors.iter().enumerate() {
+        let mut sector = data
+            .get(start..end)
+            .context("Sector outside of the archive")?
+            .to_vec();
+        if flags & FILE_ENCRYPTED != 0 {
+            crypt.decrypt(&mut sector, key.wrapping_add(index as u32));
+        }
+        let expected = if flags & FILE_SINGLE_UNIT != 0 {
+            size
+        } else {
+            let left = size.saturating_sub(file.len());
+            ensure!(left > 0, "Sector {} is past the end of the file", index);
+            left.min(sector_size)
+        };
+        // Sectors that didn't get smaller are stored as they are
+        if !compressed || sector.len() >= expected {
+            file.extend_from_slice(&sector);
+        } else if flags & FILE_IMPLODE != 0 {
+            file.extend(explode(&sector)?);
+        } else {
+            match sector.split_first() {
+                Some((&COMPRESSION_PKWARE, imploded)) => file.extend(explode(imploded)?),
+                Some((mask, _)) => bail!("Unsupported compression 0x{:02X}", mask),
+                None => bail!("Empty sector"),
+            }
+        }
+    }
+    file.truncate(size);
+    Ok(file)
+}
+
+/// Huffman code as in `blast.c` of zlib (PKWARE's codes are stored bit-inverted)
+struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+const MAX_BITS: usize = 13;
+
+impl Huffman {
+    /// Each byte of `rep` is a bit length (low nibble) repeated `(byte >> 4) + 1` times
+    fn new(rep: &[u8]) -> Self {
+        let lengths: Vec<usize> = rep
+            .iter()
+            .flat_map(|&it| vec![(it & 15) as usize; (it >> 4) as usize + 1])
+            .collect();
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in &lengths {
+            count[len] += 1;
+        }
+        let mut offsets = [0usize; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + count[len] as usize;
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len]] = symbol as u16;
+                offsets[len] += 1;
+            }
+        }
+        Self { count, symbols }
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, need: u32) -> anyhow::Result<u32> {
+        while self.count < need {
+            let byte = *self
+                .data
+                .get(self.position)
+                .context("Unexpected end of imploded data")?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << need) - 1);
+        self.buffer >>= need;
+        self.count -= need;
+        Ok(value)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> anyhow::Result<usize> {
+        let (mut code, mut first, mut index) = (0usize, 0usize, 0usize);
+        for len in 1..=MAX_BITS {
+            code |= self.bits(1)? as usize ^ 1;
+            let count = huffman.count[len] as usize;
+            if code < first + count {
+                return Ok(huffman.symbols[index + code - first] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("Invalid code in imploded data")
+    }
+}
+
+const LITERAL_LENGTHS: [u8; 98] = [
+    11, 124, 8, 7, 28, 7, 188, 13, 76, 4, 10, 8, 12, 10, 12, 10, 8, 23, 8, 9, 7, 6, 7, 8, 7, 6, 55,
+    8, 23, 24, 12, 11, 7, 9, 11, 12, 6, 7, 22, 5, 7, 24, 6, 11, 9, 6, 7, 22, 7, 11, 38, 7, 9, 8,
+    25, 11, 8, 11, 9, 12, 8, 12, 5, 38, 5, 38, 5, 11, 7, 5, 6, 21, 6, 10, 53, 8, 7, 24, 10, 27, 44,
+    253, 253, 253, 252, 252, 252, 13, 12, 45, 12, 45, 12, 61, 12, 45, 44, 173,
+];
+const LENGTH_LENGTHS: [u8; 6] = [2, 35, 36, 53, 38, 23];
+const DISTANCE_LENGTHS: [u8; 7] = [2, 20, 53, 230, 247, 151, 248];
+const LENGTH_BASE: [usize; 16] = [3, 2, 4, 5, 6, 7, 8, 9, 10, 12, 16, 24, 40, 72, 136, 264];
+const LENGTH_EXTRA: [u32; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+
+/// Decompresses PKWARE DCL imploded data
+pub fn explode(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut bits = Bits {
+        data,
+        position: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let coded_literals = match bits.bits(8)? {
+        0 => None,
+        1 => Some(Huffman::new(&LITERAL_LENGTHS)),
+        _ => bail!("Invalid literal mode in imploded data"),
+    };
+    let dictionary_bits = bits.bits(8)?;
+    ensure!(
+        (4..=6).contains(&dictionary_bits),
+        "Invalid dictionary size in imploded data"
+    );
+    let lengths = Huffman::new(&LENGTH_LENGTHS);
+    let distances = Huffman::new(&DISTANCE_LENGTHS);
+    let mut out = vec![];
+    loop {
+        if bits.bits(1)? == 1 {
+            let symbol = bits.decode(&lengths)?;
+            let len = LENGTH_BASE[symbol] + bits.bits(LENGTH_EXTRA[symbol])? as usize;
+            if len == 519 {
+                return Ok(out);
+            }
+            let extra = if len == 2 { 2 } else { dictionary_bits };
+            let distance = (bits.decode(&distances)? << extra) + bits.bits(extra)? as usize + 1;
+            ensure!(
+                distance <= out.len(),
+                "Distance too far back in imploded data"
+            );
+            // Might overlap what's copied
+            for _ in 0..len {
+                out.push(out[out.len() - distance]);
+            }
+        } else {
+            let literal = match &coded_literals {
+                Some(literals) => bits.decode(literals)? as u8,
+                None => bits.bits(8)? as u8,
+            };
+            out.push(literal);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::mpq::{
+        explode, read_file, Crypt, HashType, Huffman, DISTANCE_LENGTHS, FILE_EXISTS, FILE_IMPLODE,
+        FILE_SINGLE_UNIT, HEADER_MAGIC, LENGTH_LENGTHS, LITERAL_LENGTHS, MAX_BITS,
+    };
+
+    fn encrypt(crypt: &Crypt, data: &mut [u8], mut key: u32) {
+        let mut seed: u32 = 0xEEEE_EEEE;
+        for chunk in data.chunks_exact_mut(4) {
+            seed = seed.wrapping_add(crypt.table[0x400 + (key & 0xFF) as usize]);
+            let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            chunk.copy_from_slice(&(value ^ key.wrapping_add(seed)).to_le_bytes());
+            key = ((!key << 21).wrapping_add(0x1111_1111)) | (key >> 11);
+            seed = value
+                .wrapping_add(seed)
+                .wrapping_add(seed << 5)
+                .wrapping_add(3);
+        }
+    }
+
+    /// An archive with a single file, `stored` as it is in the archive
+    pub fn archive(name: &str, flags: u32, stored: &[u8], size: usize) -> Vec<u8> {
+        let crypt = Crypt::new();
+        let mut hash_table = vec![0xFF; 4 * 16];
+        let slot = crypt.hash(name, HashType::TableOffset) as usize % 4 * 16;
+        for (offset, value) in [
+            crypt.hash(name, HashType::NameA),
+            crypt.hash(name, HashType::NameB),
+            0,
+            0,
+        ]
+        .iter()
+        .enumerate()
+        {
+            hash_table[slot + offset * 4..slot + offset * 4 + 4]
+                .copy_from_slice(&value.to_le_bytes());
+        }
+        let data_offset = 32 + 4 * 16 + 16;
+        let mut block_table = Vec::new();
+        for value in [data_offset, stored.len() as u32, size as u32, flags] {
+            block_table.extend(value.to_le_bytes());
+        }
+        encrypt(
+            &crypt,
+            &mut hash_table,
+            crypt.hash("(hash table)", HashType::FileKey),
+        );
+        encrypt(
+            &crypt,
+            &mut block_table,
+            crypt.hash("(block table)", HashType::FileKey),
+        );
+
+        // Some junk before the header, as in maps with a (fake) executable up front
+        let mut archive = vec![0; 512];
+        archive.extend(HEADER_MAGIC);
+        for value in [
+            32,
+            data_offset + stored.len() as u32,
+            3 << 16,
+            32,
+            32 + 64,
+            4,
+            1,
+        ] {
+            archive.extend(value.to_le_bytes());
+        }
+        archive.extend(hash_table);
+        archive.extend(block_table);
+        archive.extend(stored);
+        archive
+    }
+
+    #[test]
+    fn test_read_file() {
+        let crypt = Crypt::new();
+        assert_eq!(crypt.hash("(hash table)", HashType::FileKey), 0xC3AF3770);
+        assert_eq!(crypt.hash("(block table)", HashType::FileKey), 0xEC83B3A3);
+
+        let content = b"Hello MPQ";
+        let mpq = archive(
+            "staredit\\scenario.chk",
+            FILE_EXISTS | FILE_SINGLE_UNIT,
+            content,
+            content.len(),
+        );
+        // Names are case insensitive
+        assert_eq!(read_file(&mpq, "STAREDIT\\Scenario.chk").unwrap(), content);
+        assert!(read_file(&mpq, "(listfile)").is_err());
+        assert!(read_file(b"not an archive", "(listfile)").is_err());
+
+        let mut imploded = Vec::new();
+        for offset in [8u32, 16] {
+            imploded.extend(offset.to_le_bytes());
+        }
+        imploded.extend([0x00, 0x04, 0x82, 0x24, 0x25, 0x8f, 0x80, 0x7f]);
+        let mpq = archive("file", FILE_EXISTS | FILE_IMPLODE, &imploded, 13);
+        assert_eq!(read_file(&mpq, "file").unwrap(), b"AIAIAIAIAIAIA");
+
+        // More sectors stored than the file has bytes
+        let mpq = archive("file", FILE_EXISTS, &[0; 4097], 10);
+        assert!(read_file(&mpq, "file").is_err());
+    }
+
+    #[test]
+    fn test_explode() {
+        // The example of zlib's blast.c
+        assert_eq!(
+            explode(&[0x00, 0x04, 0x82, 0x24, 0x25, 0x8f, 0x80, 0x7f]).unwrap(),
+            b"AIAIAIAIAIAIA"
+        );
+        assert!(explode(&[0x00, 0x04, 0x82]).is_err());
+        assert!(explode(&[0x02, 0x04]).is_err());
+    }
+
+    #[test]
+    fn test_codes_are_complete() {
+        for (rep, symbols) in [
+            (&LITERAL_LENGTHS[..], 256),
+            (&LENGTH_LENGTHS[..], 16),
+            (&DISTANCE_LENGTHS[..], 64),
+        ] {
+            let huffman = Huffman::new(rep);
+            assert_eq!(huffman.symbols.len(), symbols);
+            // Neither over- nor undersubscribed
+            let left =
+                (1..=MAX_BITS).fold(1i32, |left, len| (left << 1) - huffman.count[len] as i32);
+            assert_eq!(left, 0);
+        }
+    }
+}
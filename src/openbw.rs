@@ -0,0 +1,97 @@
+use std::process::Command;
+
+use anyhow::ensure;
+
+use crate::botsetup::{BotSetup, LaunchBuilder};
+use crate::{AutoMenu, BwapiConnectMode, BwapiIni, BwapiLanMode, GameConfig};
+
+#[derive(Debug)]
+pub enum OpenBwConnectMode {
+    Host {
+        map: String,
+        min_players: usize,
+        max_players: usize,
+    },
+    Join,
+}
+
+/// Runs a bot with OpenBW's `BWAPILauncher` instead of StarCraft
+pub struct OpenBw {
+    pub bot_setup: BotSetup,
+    pub game_name: String,
+    pub connect_mode: OpenBwConnectMode,
+    /// Show the OpenBW UI
+    pub headful: bool,
+}
+
+impl LaunchBuilder for OpenBw {
+    fn bwapi_ini(&self) -> BwapiIni {
+        let bot_base_path = &self.bot_setup.bot_base_path;
+        BwapiIni {
+            auto_menu: AutoMenu::Lan {
+                name: self.bot_setup.character_name(),
+                game_name: self.game_name.clone(),
+                race: self.bot_setup.lan_race(),
+                connect_mode: match &self.connect_mode {
+                    OpenBwConnectMode::Host {
+                        map,
+                        min_players,
+                        max_players,
+                    } => BwapiConnectMode::Host {
+                        // The launcher runs in the OpenBW folder, not the bot folder
+                        map: Some(
+                            self.bot_setup
+                                .starcraft_path
+                                .join(map)
+                                .to_string_lossy()
+                                .to_string(),
+                        ),
+                        min_players: *min_players,
+                        max_players: *max_players,
+                    },
+                    OpenBwConnectMode::Join => BwapiConnectMode::Join,
+                },
+                lan_mode: BwapiLanMode::LocalPC,
+                auto_restart: self.bot_setup.auto_restart,
+            },
+            // Keep replays with the bot, as with StarCraft
+            replay_template: match &self.bot_setup.replay_root {
+                Some(_) => self.bot_setup.replay_template.clone(),
+                None => self
+                    .bot_setup
+                    .replay_template
+                    .as_ref()
+                    .map(|template| bot_base_path.join(template).to_string_lossy().to_string()),
+            },
+            ..BwapiIni::from(&self.bot_setup)
+        }
+    }
+
+    fn build_command(&self, _game_config: &GameConfig) -> anyhow::Result<Command> {
+        ensure!(
+            self.bot_setup.starcraft_exe.exists(),
+            "Could not find '{}'",
+            self.bot_setup.starcraft_exe.to_string_lossy()
+        );
+        let bwapi_data = self.bot_setup.bot_base_path.join("bwapi-data");
+        ensure!(
+            bwapi_data.exists(),
+            "Missing '{}' - please read the instructions on how to setup a bot.",
+            bwapi_data.to_string_lossy()
+        );
+        let bwapi_ini = self.bot_setup.write_bwapi_ini(&self.bwapi_ini())?;
+
+        // BWAPI is built into the launcher, no need for injecting it
+        let mut cmd = self
+            .bot_setup
+            .sandbox
+            .wrap_executable(&self.bot_setup.starcraft_exe);
+        cmd.env("BWAPI_CONFIG_INI", &*bwapi_ini.to_string_lossy());
+        // All bots of a game run on this machine
+        cmd.env("OPENBW_LAN_MODE", "LOCAL");
+        cmd.env("OPENBW_ENABLE_UI", if self.headful { "1" } else { "0" });
+        // OpenBW expects the MPQs in the working directory
+        cmd.current_dir(&self.bot_setup.starcraft_path);
+        Ok(cmd)
+    }
+}
@@ -0,0 +1,54 @@
+use std::io;
+
+use winapi::shared::minwindef::FALSE;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::{OpenProcess, SetPriorityClass};
+use winapi::um::winbase::{
+    SetProcessAffinityMask, ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS,
+    HIGH_PRIORITY_CLASS, IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS,
+};
+use winapi::um::winnt::{HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_SET_INFORMATION};
+
+use crate::ProcessPriority;
+
+/// Runs `f` with a handle of the process, errors are the last OS error
+fn with_process(process_id: u32, f: impl FnOnce(HANDLE) -> bool) -> io::Result<()> {
+    unsafe {
+        let process = OpenProcess(
+            PROCESS_SET_INFORMATION | PROCESS_QUERY_INFORMATION,
+            FALSE,
+            process_id,
+        );
+        if process.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let result = if f(process) {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+        CloseHandle(process);
+        result
+    }
+}
+
+/// Lets the process only run on `cores` (0 is the first core)
+pub fn set_affinity(process_id: u32, cores: &[usize]) -> io::Result<()> {
+    let mask = cores.iter().fold(0u32, |mask, core| mask | 1 << core);
+    with_process(process_id, |process| unsafe {
+        SetProcessAffinityMask(process, mask) != 0
+    })
+}
+
+pub fn set_priority(process_id: u32, priority: ProcessPriority) -> io::Result<()> {
+    let priority_class = match priority {
+        ProcessPriority::Idle => IDLE_PRIORITY_CLASS,
+        ProcessPriority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+        ProcessPriority::Normal => NORMAL_PRIORITY_CLASS,
+        ProcessPriority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+        ProcessPriority::High => HIGH_PRIORITY_CLASS,
+    };
+    with_process(process_id, |process| unsafe {
+        SetPriorityClass(process, priority_class) != 0
+    })
+}
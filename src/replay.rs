@@ -0,0 +1,317 @@
+//! Just enough of the replay format (up to StarCraft 1.20) to tell who left the game, and
+//! compressing replays
+
+use std::collections::HashSet;
+use std::fs::{remove_file, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::mpq::explode;
+
+/// Replays of StarCraft 1.21+ (zlib compressed) start with `seRS` instead
+const REPLAY_ID: &[u8; 4] = b"reRS";
+const HEADER_LEN: usize = 0x279;
+const PLAYERS_OFFSET: usize = 0xA1;
+const PLAYER_LEN: usize = 36;
+/// Sections are compressed in chunks of this size
+const CHUNK_LEN: usize = 8192;
+
+const PLAYER_COMPUTER: u8 = 1;
+const PLAYER_HUMAN: u8 = 2;
+
+const CMD_LEAVE_GAME: u8 = 0x57;
+
+fn u32_at(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("Unexpected end of the replay")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads the replay's sections one after the other
+struct Sections<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Sections<'_> {
+    /// A section of `len` bytes: checksum, chunk count and the chunks - imploded, or stored if
+    /// compressing wouldn't save anything
+    fn next(&mut self, len: usize) -> anyhow::Result<Vec<u8>> {
+        let chunks = u32_at(self.data, self.position + 4)?;
+        self.position += 8;
+        let mut section = vec![];
+        for _ in 0..chunks {
+            let chunk_len = u32_at(self.data, self.position)? as usize;
+            self.position += 4;
+            let chunk = self
+                .data
+                .get(self.position..self.position.saturating_add(chunk_len))
+                .context("Unexpected end of the replay")?;
+            self.position += chunk_len;
+            let expected = (len - section.len()).min(CHUNK_LEN);
+            if chunk_len == expected {
+                section.extend_from_slice(chunk);
+            } else {
+                let mut exploded = explode(chunk)?;
+                exploded.truncate(expected);
+                section.extend(exploded);
+            }
+        }
+        ensure!(section.len() == len, "Replay section is incomplete");
+        Ok(section)
+    }
+}
+
+/// Bytes after the command type, `None` for unknown commands
+fn command_len(kind: u8, data: &[u8]) -> Option<usize> {
+    Some(match kind {
+        // Select, shift select and shift deselect: count, unit tags
+        0x09..=0x0B => 1 + 2 * *data.first()? as usize,
+        // 1.21 select
+        0x63..=0x65 => 1 + 4 * *data.first()? as usize,
+        // Save and load game: a file name after 4 bytes
+        0x06 | 0x07 => 4 + data.get(4..)?.iter().position(|&b| b == 0)? + 1,
+        0x05
+        | 0x08
+        | 0x10
+        | 0x11
+        | 0x18
+        | 0x19
+        | 0x1B..=0x1D
+        | 0x27
+        | 0x2A
+        | 0x2E
+        | 0x31
+        | 0x33
+        | 0x34
+        | 0x36
+        | 0x38
+        | 0x39
+        | 0x3C
+        | 0x54
+        | 0x5A
+        | 0x5B => 0,
+        0x0F
+        | 0x1A
+        | 0x1E
+        | 0x21
+        | 0x22
+        | 0x25
+        | 0x26
+        | 0x28
+        | 0x2B..=0x2D
+        | 0x30
+        | 0x32
+        | 0x3A
+        | 0x3B
+        | 0x3D
+        | 0x42
+        | 0x43
+        | 0x55
+        | 0x57 => 1,
+        0x0D | 0x13 | 0x1F | 0x20 | 0x23 | 0x29 | 0x35 | 0x41 | 0x44 | 0x45 => 2,
+        0x0E | 0x12 | 0x2F | 0x58 | 0x62 => 4,
+        0x3E => 5,
+        0x37 => 6,
+        0x0C | 0x3F => 7,
+        0x14 | 0x56 => 9,
+        0x15 => 10,
+        0x60 => 11,
+        0x48 | 0x61 => 12,
+        0x40 => 17,
+        0x5C => 81,
+        _ => return None,
+    })
+}
+
+/// Ids of players who left the game, in the order they left
+fn leavers(commands: &[u8]) -> Vec<u8> {
+    let mut leavers = vec![];
+    let mut position = 0;
+    // Frame, then a block of commands
+    while let Some(&block_len) = commands.get(position + 4) {
+        let block_start = position + 5;
+        let block_end = (block_start + block_len as usize).min(commands.len());
+        let mut command = block_start;
+        while command + 2 <= block_end {
+            let (player, kind) = (commands[command], commands[command + 1]);
+            if kind == CMD_LEAVE_GAME {
+                leavers.push(player);
+            }
+            // Later versions might know more commands, skip the rest of the block
+            match command_len(kind, &commands[command + 2..block_end]) {
+                Some(len) => command += 2 + len,
+                None => break,
+            }
+        }
+        position = block_end;
+    }
+    leavers
+}
+
+/// Name of the only player who did not leave the game, `None` if it's not that clear
+fn winner_of(replay: &[u8]) -> anyhow::Result<Option<String>> {
+    let mut sections = Sections {
+        data: replay,
+        position: 0,
+    };
+    let id = sections.next(4)?;
+    if id != REPLAY_ID {
+        bail!("Unsupported replay format (only up to StarCraft 1.20)");
+    }
+    let header = sections.next(HEADER_LEN)?;
+    let commands_len = u32_at(&sections.next(4)?, 0)? as usize;
+    let commands = sections.next(commands_len)?;
+
+    let leavers: HashSet<_> = leavers(&commands).into_iter().collect();
+    if leavers.is_empty() {
+        return Ok(None);
+    }
+    let remaining: Vec<_> = header[PLAYERS_OFFSET..PLAYERS_OFFSET + 12 * PLAYER_LEN]
+        .chunks_exact(PLAYER_LEN)
+        .filter(|player| matches!(player[8], PLAYER_COMPUTER | PLAYER_HUMAN))
+        .filter(|player| !leavers.contains(&player[4]))
+        .map(|player| {
+            let name = &player[11..];
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..len]).to_string()
+        })
+        .collect();
+    Ok(match remaining.as_slice() {
+        [winner] => Some(winner.clone()),
+        _ => None,
+    })
+}
+
+/// Player name of the winner of the replay at `path`: The only one who didn't leave the game
+pub fn winner(path: &Path) -> anyhow::Result<Option<String>> {
+    winner_of(&std::fs::read(path)?)
+        .with_context(|| format!("Could not read replay '{}'", path.to_string_lossy()))
+}
+
+/// Zips the replay at `path` next to it (as `.rep.zip`) and deletes it, returns the zip
+pub fn compress(path: &Path) -> anyhow::Result<PathBuf> {
+    let zip_path = path.with_extension("rep.zip");
+    let name = path
+        .file_name()
+        .context("Replay without file name")?
+        .to_string_lossy();
+    let mut rep =
+        File::open(path).with_context(|| format!("Could not open '{}'", path.to_string_lossy()))?;
+    let compressed = (|| -> anyhow::Result<()> {
+        let mut zip = ZipWriter::new(File::create(&zip_path)?);
+        zip.start_file(
+            name,
+            FileOptions::default().compression_method(CompressionMethod::Deflated),
+        )?;
+        std::io::copy(&mut rep, &mut zip)?;
+        zip.finish()?;
+        Ok(())
+    })();
+    if let Err(e) = compressed {
+        remove_file(&zip_path).ok();
+        return Err(e).with_context(|| format!("Could not compress '{}'", path.to_string_lossy()));
+    }
+    // Windows can't delete open files
+    drop(rep);
+    remove_file(path).with_context(|| format!("Could not delete '{}'", path.to_string_lossy()))?;
+    Ok(zip_path)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::replay::{compress, leavers, winner_of, HEADER_LEN, PLAYERS_OFFSET, PLAYER_LEN};
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    /// A section with a single stored chunk
+    fn section(data: &[u8]) -> Vec<u8> {
+        let mut section = vec![0; 4];
+        section.extend(1u32.to_le_bytes());
+        section.extend((data.len() as u32).to_le_bytes());
+        section.extend(data);
+        section
+    }
+
+    /// Replay of a game of `players` (id, name), with these `commands` (frame, player, command)
+    pub fn replay(players: &[(u8, &str)], commands: &[(u32, u8, &[u8])]) -> Vec<u8> {
+        let mut header = vec![0; HEADER_LEN];
+        for (i, (id, name)) in players.iter().enumerate() {
+            let player = &mut header[PLAYERS_OFFSET + i * PLAYER_LEN..][..PLAYER_LEN];
+            player[4] = *id;
+            player[8] = 2;
+            player[11..11 + name.len()].copy_from_slice(name.as_bytes());
+        }
+        let mut command_data = vec![];
+        for (frame, player, command) in commands {
+            command_data.extend(frame.to_le_bytes());
+            command_data.push(command.len() as u8 + 1);
+            command_data.push(*player);
+            command_data.extend(*command);
+        }
+        let mut replay = section(b"reRS");
+        replay.extend(section(&header));
+        replay.extend(section(&(command_data.len() as u32).to_le_bytes()));
+        replay.extend(section(&command_data));
+        replay
+    }
+
+    #[test]
+    fn test_leavers() {
+        let commands = [
+            // Select 2 units, then stop
+            10, 0, 0, 0, 10, 1, 0x09, 2, 1, 0, 2, 0, 1, 0x1A, 0, //
+            // Unknown command, leave game is skipped with the rest of the block
+            20, 0, 0, 0, 5, 0, 0xF0, 0, 0x57, 1, //
+            30, 0, 0, 0, 3, 1, 0x57, 1,
+        ];
+        assert_eq!(leavers(&commands), vec![1]);
+    }
+
+    #[test]
+    fn test_winner() {
+        let players = [(0, "NitekatT"), (1, "MarineHell")];
+        let stop: &[u8] = &[0x1A, 0];
+        let leave: &[u8] = &[0x57, 1];
+        let winner = |replay: Vec<u8>| winner_of(&replay).unwrap();
+        assert_eq!(
+            winner(replay(&players, &[(100, 0, stop), (200, 1, leave)])),
+            Some("NitekatT".to_string())
+        );
+        assert_eq!(winner(replay(&players, &[(100, 0, stop)])), None);
+        assert!(winner_of(b"not a replay").is_err());
+        let mut newer = replay(&players, &[]);
+        newer[12..16].copy_from_slice(b"seRS");
+        assert!(winner_of(&newer).is_err());
+    }
+
+    #[test]
+    fn test_compress() {
+        let dir = std::env::temp_dir().join("bwaishotgun-test-compress-replay");
+        remove_dir_all(&dir).ok();
+        create_dir_all(&dir).unwrap();
+        let rep = dir.join("game.rep");
+        let data = replay(&[(0, "NitekatT")], &[]);
+        std::fs::write(&rep, &data).unwrap();
+        let zip = compress(&rep).unwrap();
+        assert_eq!(zip, dir.join("game.rep.zip"));
+        assert!(!rep.exists());
+        let mut archive = ZipArchive::new(File::open(&zip).unwrap()).unwrap();
+        let mut unzipped = vec![];
+        archive
+            .by_name("game.rep")
+            .unwrap()
+            .read_to_end(&mut unzipped)
+            .unwrap();
+        assert_eq!(unzipped, data);
+        // Already compressed, the zip is kept
+        assert!(compress(&rep).is_err());
+        assert!(zip.exists());
+        remove_dir_all(&dir).ok();
+    }
+}
@@ -1,11 +1,11 @@
 use serde::Deserialize;
 use std::fs::{create_dir_all, File};
-use std::io::copy;
+use std::io::{copy, Read, Seek};
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context};
 use hex_literal::hex;
-use log::info;
+use log::{debug, info, warn};
 use registry::{Hive, Security};
 use sha2::{Digest, Sha256};
 use zip::ZipArchive;
@@ -15,6 +15,29 @@ use crate::{base_folder, download_folder, internal_scbw_folder};
 const SCBW_URL: &str = "http://www.cs.mun.ca/~dchurchill/startcraft/scbw_bwapi440.zip";
 const SCBW_ZIP_HASH: [u8; 32] =
     hex!("C7FB49E6C170270192ABA1610F25105BF077A52E556B7A4E684484079FA9FA93");
+/// Where the StarCraft installer puts it, if it's not in the registry
+const DEFAULT_STARCRAFT_DIR: &str = r"C:\Program Files (x86)\StarCraft";
+
+/// Extracts all files of the zip archive to `target`, skipping entries pointing outside of it
+pub fn unzip(archive: impl Read + Seek, target: &Path) -> anyhow::Result<()> {
+    let mut zip = ZipArchive::new(archive)?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let outpath = match file.enclosed_name() {
+            Some(path) => target.join(path),
+            None => continue,
+        };
+        if file.is_dir() {
+            create_dir_all(&outpath)?;
+        } else {
+            if let Some(parent) = outpath.parent() {
+                create_dir_all(parent)?;
+            }
+            copy(&mut file, &mut File::create(outpath)?)?;
+        }
+    }
+    Ok(())
+}
 
 #[derive(Deserialize, Debug)]
 pub enum StarCraftInstallation {
@@ -30,64 +53,81 @@ impl Default for StarCraftInstallation {
 }
 
 impl StarCraftInstallation {
-    pub fn ensure_path(&self) -> anyhow::Result<PathBuf> {
-        match self {
-            StarCraftInstallation::Search => Self::locate_starcraft(),
-            StarCraftInstallation::Internal => {
-                let scbw_folder = internal_scbw_folder();
-                if scbw_folder.exists() {
-                    info!("Using internal StarCraft");
-                } else {
-                    let path = download_folder()?.join("scbw_bwapi440.zip");
-                    let file = if !Self::check_scbw_zip_hash(&path)? {
-                        info!(
-                            "Downloading StarCraft 1.16.1 from '{}' to '{}'",
-                            SCBW_URL,
-                            path.to_string_lossy()
-                        );
-                        let mut file = File::create(&path)?;
-                        reqwest::blocking::get(SCBW_URL)?.copy_to(&mut file)?;
-                        ensure!(
-                            Self::check_scbw_zip_hash(&path)?,
-                            "Hash check of downloaded SCBW failed, aborting!"
-                        );
-                        file
-                    } else {
-                        File::open(&path)?
-                    };
+    /// Finds StarCraft, in this order: `starcraft_dir` (of the command line), the `STARCRAFT_DIR`
+    /// environment variable, 'shotgun.toml', the registry and the default install folder
+    pub fn ensure_path(&self, starcraft_dir: Option<&Path>) -> anyhow::Result<PathBuf> {
+        let (path, source) = self.find(starcraft_dir)?;
+        validate_starcraft_dir(&path).with_context(|| {
+            format!("The StarCraft folder of {} is invalid. Set 'starcraft_path' in 'shotgun.toml', '--starcraft-dir' or STARCRAFT_DIR to the folder of your StarCraft installation", source)
+        })?;
+        info!(
+            "Using StarCraft in '{}' (from {})",
+            path.to_string_lossy(),
+            source
+        );
+        Ok(path)
+    }
 
-                    info!(
-                        "Unzipping '{}' to '{}'",
-                        path.to_string_lossy(),
-                        scbw_folder.to_string_lossy()
-                    );
-                    let mut zip = ZipArchive::new(file)?;
-                    for i in 0..zip.len() {
-                        let mut file = zip.by_index(i)?;
-                        let outpath = match file.enclosed_name() {
-                            Some(path) => scbw_folder.join(path),
-                            None => continue,
-                        };
-                        if file.is_dir() {
-                            create_dir_all(&outpath)?;
-                        } else {
-                            if let Some(parent) = outpath.parent() {
-                                create_dir_all(parent)?;
-                            }
-                            copy(&mut file, &mut File::create(outpath)?)?;
-                        }
+    /// Where `ensure_path` looks for StarCraft and where it got that from, without checking
+    /// there is one
+    pub fn find(&self, starcraft_dir: Option<&Path>) -> anyhow::Result<(PathBuf, &'static str)> {
+        Ok(if let Some(starcraft_dir) = starcraft_dir {
+            (starcraft_dir.to_path_buf(), "'--starcraft-dir'")
+        } else if let Some(starcraft_dir) = std::env::var_os("STARCRAFT_DIR") {
+            (PathBuf::from(starcraft_dir), "STARCRAFT_DIR")
+        } else {
+            match self {
+                StarCraftInstallation::Search => match Self::locate_starcraft() {
+                    Ok(path) => (path, "the registry"),
+                    Err(e) => {
+                        debug!("{:#}", e);
+                        (DEFAULT_STARCRAFT_DIR.into(), "the default install folder")
                     }
-                    info!("Installing SNP_DirectIP.snp");
-                    copy(
-                        &mut File::open(base_folder().join("SNP_DirectIP.snp"))?,
-                        &mut File::create(scbw_folder.join("SNP_DirectIP.snp"))?,
-                    )?;
-                    info!("SCBW setup complete");
-                }
-                Ok(scbw_folder)
+                },
+                StarCraftInstallation::Internal => (Self::internal()?, "'shotgun.toml'"),
+                StarCraftInstallation::Path(path) => (path.to_path_buf(), "'shotgun.toml'"),
             }
-            StarCraftInstallation::Path(path) => Ok(path.to_path_buf()),
+        })
+    }
+
+    /// The StarCraft of BWAIShotgun, downloaded if it's not there yet
+    fn internal() -> anyhow::Result<PathBuf> {
+        let scbw_folder = internal_scbw_folder();
+        if scbw_folder.exists() {
+            info!("Using internal StarCraft");
+        } else {
+            let path = download_folder()?.join("scbw_bwapi440.zip");
+            let file = if !Self::check_scbw_zip_hash(&path)? {
+                info!(
+                    "Downloading StarCraft 1.16.1 from '{}' to '{}'",
+                    SCBW_URL,
+                    path.to_string_lossy()
+                );
+                let mut file = File::create(&path)?;
+                reqwest::blocking::get(SCBW_URL)?.copy_to(&mut file)?;
+                ensure!(
+                    Self::check_scbw_zip_hash(&path)?,
+                    "Hash check of downloaded SCBW failed, aborting!"
+                );
+                file
+            } else {
+                File::open(&path)?
+            };
+
+            info!(
+                "Unzipping '{}' to '{}'",
+                path.to_string_lossy(),
+                scbw_folder.to_string_lossy()
+            );
+            unzip(file, &scbw_folder)?;
+            info!("Installing SNP_DirectIP.snp");
+            copy(
+                &mut File::open(base_folder().join("SNP_DirectIP.snp"))?,
+                &mut File::create(scbw_folder.join("SNP_DirectIP.snp"))?,
+            )?;
+            info!("SCBW setup complete");
         }
+        Ok(scbw_folder)
     }
 
     fn locate_starcraft() -> anyhow::Result<PathBuf> {
@@ -111,3 +151,46 @@ impl StarCraftInstallation {
         Ok(hash.as_slice() == SCBW_ZIP_HASH)
     }
 }
+
+/// StarCraft.exe - or just the MPQs, for OpenBW - and the maps
+fn validate_starcraft_dir(path: &Path) -> anyhow::Result<()> {
+    ensure!(
+        path.is_dir(),
+        "'{}' is not a folder",
+        path.to_string_lossy()
+    );
+    ensure!(
+        path.join("StarCraft.exe").is_file() || path.join("StarDat.mpq").is_file(),
+        "Neither 'StarCraft.exe' nor 'StarDat.mpq' found in '{}'",
+        path.to_string_lossy()
+    );
+    if !path.join("maps").is_dir() {
+        warn!("'{}' has no 'maps' folder", path.to_string_lossy());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::setup::{validate_starcraft_dir, StarCraftInstallation};
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    #[test]
+    fn test_starcraft_dir() {
+        let dir = std::env::temp_dir().join("bwaishotgun-test-starcraft-dir");
+        remove_dir_all(&dir).ok();
+        create_dir_all(dir.join("maps")).unwrap();
+        assert!(validate_starcraft_dir(&dir.join("missing")).is_err());
+        assert!(validate_starcraft_dir(&dir).is_err());
+        write(dir.join("StarCraft.exe"), b"").unwrap();
+        assert!(validate_starcraft_dir(&dir).is_ok());
+        // The command line wins over everything else
+        assert_eq!(
+            StarCraftInstallation::Path("elsewhere".into())
+                .ensure_path(Some(&dir))
+                .unwrap(),
+            dir
+        );
+        remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,524 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Context};
+use log::{error, info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::gameresult::GameResult;
+use crate::map::map_title;
+use crate::{interrupted, BotLaunchFailed, GameConfig, GameType, Shotgun};
+
+/// Round robin: Every bot plays against every other bot, `rounds` times
+#[derive(Debug)]
+pub struct Tournament {
+    pub rounds: u32,
+    /// Games played at the same time
+    pub max_parallel: usize,
+    /// Also write the standings here
+    pub csv: Option<PathBuf>,
+    /// Pause after a game ended, before the next one starts
+    pub inter_game_delay: Duration,
+}
+
+/// Two bots play up to `best_of` games, until one of them won the majority
+#[derive(Debug)]
+pub struct Series {
+    pub best_of: u32,
+    /// Pause after a game ended, before the next one starts
+    pub inter_game_delay: Duration,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SeriesScore {
+    /// Wins of the first and the second bot
+    pub wins: (u32, u32),
+    pub draws: u32,
+}
+
+impl SeriesScore {
+    pub fn games(&self) -> u32 {
+        self.wins.0 + self.wins.1 + self.draws
+    }
+
+    /// Index of the bot that won more than half of `best_of` games
+    pub fn clinched(&self, best_of: u32) -> Option<usize> {
+        if self.wins.0 > best_of / 2 {
+            Some(0)
+        } else if self.wins.1 > best_of / 2 {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    /// Once all games are played, the bot with more wins takes the series
+    pub fn winner(&self, best_of: u32) -> Option<usize> {
+        self.clinched(best_of).or(if self.games() < best_of {
+            None
+        } else if self.wins.0 > self.wins.1 {
+            Some(0)
+        } else if self.wins.1 > self.wins.0 {
+            Some(1)
+        } else {
+            None
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Standing {
+    pub name: String,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct Standings {
+    standings: Vec<Standing>,
+}
+
+impl Standings {
+    fn standing(&mut self, name: &str) -> &mut Standing {
+        let index = match self.standings.iter().position(|it| it.name == name) {
+            Some(index) => index,
+            None => {
+                self.standings.push(Standing {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.standings.len() - 1
+            }
+        };
+        &mut self.standings[index]
+    }
+
+    pub fn record_win(&mut self, winner: &str, loser: &str) {
+        self.standing(winner).wins += 1;
+        self.standing(loser).losses += 1;
+    }
+
+    pub fn record_draw(&mut self, a: &str, b: &str) {
+        self.standing(a).draws += 1;
+        self.standing(b).draws += 1;
+    }
+
+    /// Most wins first, then least losses
+    pub fn sorted(&self) -> Vec<Standing> {
+        let mut standings = self.standings.clone();
+        standings.sort_by(|a, b| {
+            b.wins
+                .cmp(&a.wins)
+                .then(a.losses.cmp(&b.losses))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        standings
+    }
+
+    pub fn write_table(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let width = self
+            .standings
+            .iter()
+            .map(|it| it.name.len())
+            .max()
+            .unwrap_or_default()
+            .max(4);
+        writeln!(
+            out,
+            "{:width$} {:>5} {:>5} {:>5}",
+            "Bot", "Win", "Loss", "Draw"
+        )?;
+        for standing in self.sorted() {
+            writeln!(
+                out,
+                "{:width$} {:>5} {:>5} {:>5}",
+                standing.name, standing.wins, standing.losses, standing.draws
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "bot,wins,losses,draws")?;
+        for standing in self.sorted() {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                standing.name, standing.wins, standing.losses, standing.draws
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Tournament {
+    /// Indices of the bots playing each game, sides are swapped every other round
+    pub fn pairings(&self, bot_count: usize) -> Vec<(usize, usize)> {
+        let mut pairings = vec![];
+        for round in 0..self.rounds {
+            for a in 0..bot_count {
+                for b in a + 1..bot_count {
+                    pairings.push(if round % 2 == 0 { (a, b) } else { (b, a) });
+                }
+            }
+        }
+        pairings
+    }
+
+    /// Plays all games with the bots of `game_config`, up to `max_parallel` at the same time
+    pub fn run(
+        &self,
+        shotgun: &mut Shotgun,
+        game_config: &GameConfig,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<()> {
+        let GameType::Melee(bots) = &game_config.game_type;
+        let mut pending: VecDeque<_> = self.pairings(bots.len()).into();
+        let game_count = pending.len();
+        let mut standings = Standings::default();
+        // A bot can't play two games at once, they share the bot folder
+        let mut busy = HashSet::new();
+        let mut game_number = 0;
+        let mut played = 0;
+        let mut error = None;
+        let started = Instant::now();
+        thread::scope(|scope| -> anyhow::Result<()> {
+            let (results, finished) = mpsc::channel();
+            loop {
+                while error.is_none() && !interrupted() && busy.len() / 2 < self.max_parallel {
+                    let next = match pending
+                        .iter()
+                        .position(|(a, b)| !busy.contains(a) && !busy.contains(b))
+                    {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let pairing = pending[next];
+                    // Each bot takes a slot of the game table, starting a game without would block it
+                    let uses_game_table =
+                        game_config.starcraft_flavor.uses_game_table() && !game_config.dry_run;
+                    let (slot_count, free_slots) = if uses_game_table {
+                        shotgun.slots()?
+                    } else {
+                        (usize::MAX, usize::MAX)
+                    };
+                    if busy.len() + 2 > slot_count || free_slots < 2 {
+                        ensure!(
+                            !busy.is_empty(),
+                            "Only {} free slots in the game table, is another StarCraft running?",
+                            free_slots
+                        );
+                        break;
+                    }
+                    pending.remove(next);
+                    busy.insert(pairing.0);
+                    busy.insert(pairing.1);
+                    game_number += 1;
+
+                    let (a, b) = (&bots[pairing.0], &bots[pairing.1]);
+                    info!(
+                        "Game {} of {}: '{}' vs '{}'",
+                        game_number, game_count, a.name, b.name
+                    );
+                    let mut game = game_config.clone();
+                    game.game_type = GameType::Melee(vec![a.clone(), b.clone()]);
+                    game.resolve_map_pool(&shotgun.starcraft_path, rng)?;
+                    game.resolve_game_name(rng);
+                    // Keeps seeded tournaments reproducible, no matter the order games end in
+                    let mut game_rng = StdRng::seed_from_u64(rng.gen());
+                    let worker = shotgun.worker();
+                    let results = results.clone();
+                    scope.spawn(move || {
+                        let result = worker().play(&game, &mut game_rng);
+                        results.send((pairing, result)).ok();
+                    });
+                }
+                if busy.is_empty() {
+                    break;
+                }
+                // Timing out to check for free slots again
+                let ((a, b), result) = match finished.recv_timeout(Duration::from_secs(1)) {
+                    Ok(finished) => finished,
+                    Err(_) => continue,
+                };
+                busy.remove(&a);
+                busy.remove(&b);
+                played += 1;
+                let (a, b) = (&bots[a], &bots[b]);
+                match result {
+                    Ok(Some(result)) => record(&mut standings, &result, &a.name, &b.name),
+                    Ok(None) => (),
+                    Err(e) if interrupted() => {
+                        error.get_or_insert(e);
+                    }
+                    Err(e) => match e.downcast_ref::<BotLaunchFailed>() {
+                        Some(BotLaunchFailed(failed)) => {
+                            error!("{:?}", e);
+                            let (failed, other) = if failed == &a.name {
+                                (&a.name, &b.name)
+                            } else {
+                                (&b.name, &a.name)
+                            };
+                            warn!("Counting the game as loss of '{}'", failed);
+                            standings.record_win(other, failed);
+                        }
+                        None => {
+                            if !busy.is_empty() {
+                                error!("{:?}", e);
+                                warn!("Waiting for the running games to end");
+                            }
+                            error.get_or_insert(e);
+                        }
+                    },
+                }
+                // After recording the game, without a cool down the next one still waits for free slots
+                if !pending.is_empty() && error.is_none() {
+                    if let Err(e) = shotgun.cool_down(game_config, self.inter_game_delay, 2) {
+                        warn!("Could not cool down after the game: {:#}", e);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        if game_config.dry_run {
+            return Ok(());
+        }
+        let minutes = started.elapsed().as_secs_f64() / 60.0;
+        info!(
+            "Played {} games in {:.1} minutes ({:.1} games per hour)",
+            played,
+            minutes,
+            played as f64 / minutes.max(f64::EPSILON) * 60.0
+        );
+        standings.write_table(&mut std::io::stdout())?;
+        if let Some(csv) = &self.csv {
+            standings
+                .write_csv(&mut File::create(csv)?)
+                .with_context(|| format!("Could not write '{}'", csv.to_string_lossy()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Series {
+    /// Plays the two bots of `game_config` against each other, the host changes every game
+    pub fn run(
+        &self,
+        shotgun: &mut Shotgun,
+        game_config: &GameConfig,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            !game_config.shuffle_slots,
+            "A series alternates the host every game, shuffling the slots would undo that"
+        );
+        let GameType::Melee(bots) = &game_config.game_type;
+        // As in `bots.toml`, results and launch failures use those too
+        let names = [&bots[0].name, &bots[1].name];
+        let mut score = SeriesScore::default();
+        for game_number in 0..self.best_of {
+            if score.clinched(self.best_of).is_some() {
+                break;
+            }
+            if game_number > 0 {
+                shotgun.cool_down(game_config, self.inter_game_delay, 2)?;
+            }
+            // The first bot hosts
+            let (host, other) = if game_number % 2 == 0 { (0, 1) } else { (1, 0) };
+            let mut game = game_config.clone();
+            game.game_type = GameType::Melee(vec![bots[host].clone(), bots[other].clone()]);
+            game.resolve_map_pool(&shotgun.starcraft_path, rng)?;
+            game.resolve_game_name(rng);
+            let map = game
+                .map
+                .as_deref()
+                .map(|it| map_title(&shotgun.starcraft_path.join(it)))
+                .unwrap_or_default();
+            info!(
+                "Game {} of up to {}: '{}' hosts '{}' on '{}'",
+                game_number + 1,
+                self.best_of,
+                names[host],
+                names[other],
+                map
+            );
+            let winner = match shotgun.play(&game, rng) {
+                Ok(Some(result)) => result
+                    .winning_bot()
+                    .and_then(|winner| names.iter().position(|&name| name == winner)),
+                Ok(None) => continue,
+                Err(e) if interrupted() => return Err(e),
+                Err(e) => match e.downcast_ref::<BotLaunchFailed>() {
+                    Some(BotLaunchFailed(failed)) => {
+                        error!("{:?}", e);
+                        warn!("Counting the game as loss of '{}'", failed);
+                        names.iter().position(|&name| name != failed)
+                    }
+                    None => return Err(e),
+                },
+            };
+            match winner {
+                Some(0) => score.wins.0 += 1,
+                Some(_) => score.wins.1 += 1,
+                None => score.draws += 1,
+            }
+            match winner {
+                Some(winner) => info!(
+                    "Game {} on '{}': '{}' won",
+                    game_number + 1,
+                    map,
+                    names[winner]
+                ),
+                None => info!("Game {} on '{}': Draw", game_number + 1, map),
+            }
+        }
+
+        if game_config.dry_run {
+            return Ok(());
+        }
+        println!(
+            "{} {} - {} {} ({} draws)",
+            names[0], score.wins.0, score.wins.1, names[1], score.draws
+        );
+        match score.winner(self.best_of) {
+            Some(winner) => println!("'{}' wins the series", names[winner]),
+            None => println!("The series is drawn"),
+        }
+        Ok(())
+    }
+}
+
+/// `a` and `b` are the names in `bots.toml`, the bots may have played under other names
+fn record(standings: &mut Standings, result: &GameResult, a: &str, b: &str) {
+    match result.winning_bot() {
+        Some(winner) if winner == a => standings.record_win(a, b),
+        Some(winner) if winner == b => standings.record_win(b, a),
+        // Timeouts or no tournament module
+        _ => standings.record_draw(a, b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::gameresult::test::bot;
+    use crate::gameresult::GameResult;
+    use crate::tournament::{record, Series, SeriesScore, Standing, Standings, Tournament};
+    use crate::{GameConfig, Shotgun};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::time::Duration;
+
+    #[test]
+    fn test_round_robin() {
+        let tournament = Tournament {
+            rounds: 2,
+            max_parallel: 1,
+            csv: None,
+            inter_game_delay: Duration::ZERO,
+        };
+        let pairings = tournament.pairings(3);
+        assert_eq!(
+            pairings,
+            vec![(0, 1), (0, 2), (1, 2), (1, 0), (2, 0), (2, 1)]
+        );
+
+        let mut standings = Standings::default();
+        standings.record_win("B", "A");
+        standings.record_draw("A", "C");
+        standings.record_win("B", "C");
+        assert_eq!(
+            standings.sorted()[0],
+            Standing {
+                name: "B".to_string(),
+                wins: 2,
+                losses: 0,
+                draws: 0
+            }
+        );
+        let mut csv = vec![];
+        standings.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "bot,wins,losses,draws\nB,2,0,0\nA,0,1,1\nC,0,1,1\n"
+        );
+    }
+
+    #[test]
+    fn test_record_renamed_bot() {
+        let mut renamed = bot("Renamed", true, Some(100));
+        renamed.config_name = "A".to_string();
+        let result = GameResult::new(
+            None,
+            None,
+            None,
+            false,
+            vec![renamed, bot("B", false, Some(90))],
+        );
+        let mut standings = Standings::default();
+        record(&mut standings, &result, "A", "B");
+        assert_eq!(
+            standings.sorted()[0],
+            Standing {
+                name: "A".to_string(),
+                wins: 1,
+                losses: 0,
+                draws: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_series_keeps_hosts() {
+        let no_starcraft = std::env::temp_dir().join("bwaishotgun-test-no-starcraft");
+        let mut shotgun = Shotgun::with_any_starcraft_dir(Some(&no_starcraft)).unwrap();
+        let game_config: GameConfig = toml::from_str(
+            "game_type = { Melee = [{ name = 'A' }, { name = 'B' }] }
+            shuffle_slots = true",
+        )
+        .unwrap();
+        let series = Series {
+            best_of: 3,
+            inter_game_delay: Duration::ZERO,
+        };
+        let err = series
+            .run(&mut shotgun, &game_config, &mut StdRng::seed_from_u64(0))
+            .unwrap_err();
+        assert!(err.to_string().contains("alternates the host"));
+    }
+
+    #[test]
+    fn test_series_score() {
+        let mut score = SeriesScore::default();
+        score.wins.0 += 1;
+        score.draws += 1;
+        score.wins.1 += 1;
+        assert_eq!(score.clinched(5), None);
+        score.wins.0 += 2;
+        assert_eq!(score.clinched(5), Some(0));
+        assert_eq!(score.games(), 5);
+
+        // Draws don't win a series
+        let drawn = SeriesScore {
+            wins: (1, 1),
+            draws: 1,
+        };
+        assert_eq!(drawn.winner(3), None);
+        let ahead = SeriesScore {
+            wins: (0, 1),
+            draws: 2,
+        };
+        assert_eq!(ahead.winner(3), Some(1));
+        assert_eq!(ahead.winner(5), None);
+    }
+}
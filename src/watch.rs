@@ -0,0 +1,63 @@
+use std::io::Write;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::bwapi::{tick_count, GameInstance, GameTableAccess};
+use crate::interrupted;
+
+/// One line of `bwaishotgun watch`
+#[derive(Serialize)]
+struct WatchLine<'a> {
+    /// `tick_count` when the table was read, `last_keep_alive_time` is on the same clock
+    tick_count: u32,
+    /// Empty if there is no game table (yet)
+    slots: &'a [GameInstance],
+}
+
+fn write_line(out: &mut impl Write, tick_count: u32, slots: &[GameInstance]) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *out, &WatchLine { tick_count, slots })?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Prints the game table every `interval` as a JSON line until interrupted, without launching anything
+pub fn run(
+    game_table_access: &mut GameTableAccess,
+    interval: Duration,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    while !interrupted() {
+        write_line(out, tick_count(), &game_table_access.instances())?;
+        std::thread::sleep(interval);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bwapi::GameInstance;
+    use crate::watch::write_line;
+
+    #[test]
+    fn test_write_line() {
+        let mut out = vec![];
+        write_line(&mut out, 5_000, &[]).unwrap();
+        write_line(
+            &mut out,
+            5_100,
+            &[GameInstance {
+                server_process_id: 42,
+                is_connected: true,
+                last_keep_alive_time: 4_900,
+            }],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"tick_count\":5000,\"slots\":[]}\n\
+             {\"tick_count\":5100,\"slots\":[{\"server_process_id\":42,\"is_connected\":true,\"last_keep_alive_time\":4900}]}\n"
+        );
+    }
+}
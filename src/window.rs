@@ -0,0 +1,37 @@
+use winapi::shared::minwindef::{BOOL, DWORD, LPARAM, TRUE};
+use winapi::shared::windef::HWND;
+use winapi::um::winuser::{
+    EnumWindows, GetWindowThreadProcessId, IsWindowVisible, ShowWindow, SW_SHOWMINNOACTIVE,
+};
+
+struct WindowSearch {
+    process_id: DWORD,
+    found: bool,
+}
+
+unsafe extern "system" fn minimize_window_of(hwnd: HWND, search: LPARAM) -> BOOL {
+    let search = &mut *(search as *mut WindowSearch);
+    let mut process_id = 0;
+    GetWindowThreadProcessId(hwnd, &mut process_id);
+    if process_id == search.process_id && IsWindowVisible(hwnd) != 0 {
+        ShowWindow(hwnd, SW_SHOWMINNOACTIVE);
+        search.found = true;
+    }
+    TRUE
+}
+
+/// Minimizes the visible windows of a process without activating another window,
+/// `false` if it has none (yet)
+pub fn minimize_windows(process_id: u32) -> bool {
+    let mut search = WindowSearch {
+        process_id,
+        found: false,
+    };
+    unsafe {
+        EnumWindows(
+            Some(minimize_window_of),
+            &mut search as *mut WindowSearch as LPARAM,
+        );
+    }
+    search.found
+}